@@ -0,0 +1,205 @@
+//! How closely people follow the testing and isolation rules
+
+use crate::{rng, Error, Households, IsolationScope, Person};
+use rand::Rng;
+
+/// How likely someone is to bend each of the rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adherence {
+    /// Probability of skipping a scheduled test
+    pub skip_test: f64,
+    /// Probability of breaking isolation for a single interaction
+    pub break_isolation: f64,
+    /// Probability of never reporting a positive result, which then doesn't trigger isolation
+    pub unreported: f64,
+}
+
+impl Adherence {
+    /// Follows every rule
+    pub const PERFECT: Adherence = Adherence {
+        skip_test: 0.,
+        break_isolation: 0.,
+        unreported: 0.,
+    };
+
+    /// Checks every probability is in [0, 1]
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, p) in &[
+            ("skip test probability", self.skip_test),
+            ("break isolation probability", self.break_isolation),
+            ("unreported probability", self.unreported),
+        ] {
+            if !(0. ..=1.).contains(p) {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, p)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Adherence {
+    fn default() -> Self {
+        Adherence::PERFECT
+    }
+}
+
+/// Draws an event of the given, validated probability, without touching the rng when it can't
+/// happen
+fn happens(probability: f64) -> bool {
+    probability > 0. && rng().gen_bool(probability)
+}
+
+impl Person {
+    /// Switches this person to the given adherence, failing when a probability is out of range
+    pub fn with_adherence(mut self, adherence: Adherence) -> Result<Person, Error> {
+        adherence.validate()?;
+        self.adherence = adherence;
+        Ok(self)
+    }
+
+    /// Does this person show up for a scheduled test?
+    pub fn takes_scheduled_test(&self) -> bool {
+        !happens(self.adherence.skip_test)
+    }
+
+    /// Does this person break isolation for an interaction?
+    pub fn breaks_isolation(&self) -> bool {
+        happens(self.adherence.break_isolation)
+    }
+
+    /// Does this person report a test result? Negative results need no reporting
+    pub(crate) fn reports(&self, positive: bool) -> bool {
+        !positive || !happens(self.adherence.unreported)
+    }
+}
+
+impl Households {
+    /// Can `a` and `b` meet on a day where the given people isolate? Whoever of them isolates
+    /// has to break isolation for this one interaction
+    pub fn can_meet(
+        &self,
+        people: &[Person],
+        isolating: &[bool],
+        scope: IsolationScope,
+        a: usize,
+        b: usize,
+    ) -> bool {
+        self.can_interact(isolating, scope, a, b)
+            || ((!isolating[a] || people[a].breaks_isolation())
+                && (!isolating[b] || people[b].breaks_isolation()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        run_single, CyclicPattern, DailyAction, Disease, IsolationPolicy, PatternDesc, Resolution,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn unreported_results() {
+        let hiding = Adherence {
+            unreported: 1.,
+            ..Adherence::PERFECT
+        };
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_adherence(hiding).unwrap();
+            me.expose(100);
+            let infection = me.get_infection().unwrap();
+            let t = infection.testable_date;
+            me.test(t, 1);
+            assert!(me.tests[0].positive);
+
+            // only symptoms make them isolate
+            assert_eq!(
+                me.isolation_start(t + 1),
+                infection.noticed_date.filter(|s| *s <= t + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_adherence() {
+        let invalid = [
+            Adherence {
+                skip_test: 1.5,
+                ..Adherence::PERFECT
+            },
+            Adherence {
+                break_isolation: f64::NAN,
+                ..Adherence::PERFECT
+            },
+            Adherence {
+                unreported: -0.1,
+                ..Adherence::PERFECT
+            },
+        ];
+        for adherence in &invalid {
+            assert!(Person::new().with_adherence(*adherence).is_err());
+        }
+    }
+
+    #[test]
+    fn broken_isolation() {
+        let households = Households::individual(2);
+        let isolating = [true, false];
+        let people = [Person::new(), Person::new()];
+        assert!(!households.can_meet(&people, &isolating, IsolationScope::Individual, 0, 1));
+
+        let rebel = Adherence {
+            break_isolation: 1.,
+            ..Adherence::PERFECT
+        };
+        let people = [Person::new().with_adherence(rebel).unwrap(), Person::new()];
+        assert!(households.can_meet(&people, &isolating, IsolationScope::Individual, 0, 1));
+
+        // a rebel visitor doesn't get someone else out of isolation
+        let people = [Person::new(), Person::new().with_adherence(rebel).unwrap()];
+        for _ in 0..1_000 {
+            assert!(!households.can_meet(&people, &isolating, IsolationScope::Individual, 0, 1));
+            assert!(!households.can_meet(&people, &isolating, IsolationScope::Individual, 1, 0));
+        }
+
+        // both isolating, both have to break the rules
+        let isolating = [true, true];
+        assert!(!households.can_meet(&people, &isolating, IsolationScope::Individual, 0, 1));
+        let people = [
+            Person::new().with_adherence(rebel).unwrap(),
+            Person::new().with_adherence(rebel).unwrap(),
+        ];
+        assert!(households.can_meet(&people, &isolating, IsolationScope::Individual, 0, 1));
+    }
+
+    #[test]
+    fn skipped_tests() {
+        let mut actions = HashMap::new();
+        actions.insert(0, vec![DailyAction::Test(0)]);
+        let pattern = PatternDesc {
+            n_people: 1,
+            cycles: vec![CyclicPattern {
+                period: 1,
+                offset: 0,
+                actions,
+            }],
+            households: Households::individual(1),
+            scope: IsolationScope::Individual,
+            resolution: Resolution::DAY,
+            adherence: Vec::new(),
+            symptoms: Vec::new(),
+            ages: Vec::new(),
+            disease: Disease::default(),
+            isolation_policy: IsolationPolicy::default(),
+        };
+
+        // someone never showing up is only found through symptoms
+        let skipping = Adherence {
+            skip_test: 1.,
+            ..Adherence::PERFECT
+        };
+        let run = run_single(&pattern.with_adherence(vec![skipping]), 0, 100, false, None).unwrap();
+        assert_eq!(run.tests_used, 0);
+        assert!(run.people[0].tests.is_empty());
+    }
+}
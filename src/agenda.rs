@@ -0,0 +1,139 @@
+//! Discrete-event scheduling, so simulations only wake up on days where something can happen
+
+use crate::{IsolationPolicy, Person, Time};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Queue of the days on which something may change, the days in between are skipped
+#[derive(Debug, Default)]
+pub struct Agenda {
+    days: BinaryHeap<Reverse<Time>>,
+    last: Option<Time>,
+    daily: bool,
+}
+
+impl Agenda {
+    /// Creates an empty agenda
+    pub fn new() -> Agenda {
+        Agenda::default()
+    }
+
+    /// Creates an agenda that wakes up every day from the first one scheduled on, like a plain
+    /// day-by-day loop. It never runs out, it's up to the simulation to stop
+    pub fn daily() -> Agenda {
+        Agenda {
+            daily: true,
+            ..Agenda::default()
+        }
+    }
+
+    /// Wakes the simulation up on the given day
+    pub fn schedule(&mut self, day: Time) {
+        self.days.push(Reverse(day));
+    }
+
+    /// Wakes the simulation up on the next day any of these people changes state
+    pub fn schedule_people(&mut self, date: Time, people: &[Person]) {
+        for day in people.iter().filter_map(|p| p.next_change(date)) {
+            self.schedule(day);
+        }
+    }
+
+    /// Pops the next day to simulate, each day is only returned once and always in order
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Time> {
+        if let (true, Some(last)) = (self.daily, self.last) {
+            self.schedule(last + 1);
+        }
+        while let Some(Reverse(day)) = self.days.pop() {
+            if self.last.is_none_or(|last| day > last) {
+                self.last = Some(day);
+                return Some(day);
+            }
+        }
+        None
+    }
+}
+
+impl Person {
+    /// First day after `date` on which this person's infection, symptoms, test results or isolation
+    /// can change, as known on that date
+    pub fn next_change(&self, date: Time) -> Option<Time> {
+        let mut days = Vec::new();
+        if let Some(infection) = &self.infection {
+            days.push(infection.date);
+            days.push(infection.testable_date);
+            days.push(infection.testable_end + 1);
+            days.push(infection.contagious_date);
+            days.push(infection.recovery_date + 1);
+            days.extend(infection.symptomatic_date);
+            days.extend(infection.noticed_date);
+        }
+        days.extend(self.next_cold());
+        days.extend(self.colds.iter().map(|c| self.cold_isolation_end(*c)));
+        days.extend(self.tests.iter().flat_map(|t| vec![t.date, t.result_date]));
+
+        if let Some(start) = self.isolation_start(date) {
+            match self.isolation_policy {
+                // release tests may be due any day
+                IsolationPolicy::TestBased { .. } if self.is_isolating(date) => days.push(date + 1),
+                _ => days.extend(self.isolation_end(start, date)),
+            }
+        }
+
+        days.into_iter().filter(|d| *d > date).min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn agenda_order() {
+        let mut agenda = Agenda::new();
+        for day in &[5, 3, 9, 3, 5] {
+            agenda.schedule(*day);
+        }
+        assert_eq!(agenda.next(), Some(3));
+        assert_eq!(agenda.next(), Some(5));
+
+        // the past is skipped
+        agenda.schedule(4);
+        assert_eq!(agenda.next(), Some(9));
+        assert_eq!(agenda.next(), None);
+
+        let mut daily = Agenda::daily();
+        daily.schedule(3);
+        daily.schedule(6);
+        assert_eq!(daily.next(), Some(3));
+        assert_eq!(daily.next(), Some(4));
+        assert_eq!(daily.next(), Some(5));
+        assert_eq!(daily.next(), Some(6));
+        assert_eq!(daily.next(), Some(7));
+    }
+
+    #[test]
+    fn person_next_change() {
+        let healthy = Person::new();
+        assert_eq!(healthy.next_change(0), None);
+
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_isolation_policy(IsolationPolicy::Fixed(7));
+            me.expose(100);
+            let infection = me.get_infection().unwrap();
+            assert_eq!(
+                me.next_change(100),
+                Some(infection.testable_date),
+                "{:?}",
+                infection
+            );
+
+            // nothing left to happen after recovery
+            let t = infection.testable_date;
+            me.test(t, 1);
+            let last = infection.recovery_date + 1;
+            assert_eq!(me.next_change(last + 7), None);
+        }
+    }
+}
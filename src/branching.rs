@@ -0,0 +1,173 @@
+//! Reproduction number and secondary attack rate, from the first generation of transmissions of
+//! single index cases
+
+use crate::{run_single, Error, PatternDesc, HORIZON};
+use std::ops::AddAssign;
+
+/// Secondary cases caused by a number of index cases
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecondaryCases {
+    /// Number of index cases that infected each number of people
+    offspring: Vec<u64>,
+    household_contacts: u64,
+    household_infections: u64,
+}
+
+impl SecondaryCases {
+    /// No index case yet
+    pub fn new() -> SecondaryCases {
+        SecondaryCases::default()
+    }
+
+    /// Adds an index case that infected `offspring` people, `household_infections` of them among
+    /// their `household_contacts` household members
+    pub fn record(
+        &mut self,
+        offspring: usize,
+        household_contacts: usize,
+        household_infections: usize,
+    ) {
+        if self.offspring.len() <= offspring {
+            self.offspring.resize(offspring + 1, 0);
+        }
+        self.offspring[offspring] += 1;
+        self.household_contacts += household_contacts as u64;
+        self.household_infections += household_infections as u64;
+    }
+
+    /// Number of index cases recorded
+    pub fn index_cases(&self) -> u64 {
+        self.offspring.iter().sum()
+    }
+
+    /// Mean number of people infected by an index case
+    pub fn reproduction_number(&self) -> f64 {
+        let infected: u64 = self
+            .offspring
+            .iter()
+            .enumerate()
+            .map(|(k, n)| k as u64 * n)
+            .sum();
+        infected as f64 / self.index_cases() as f64
+    }
+
+    /// Share of the household members of index cases they infected
+    pub fn secondary_attack_rate(&self) -> f64 {
+        self.household_infections as f64 / self.household_contacts as f64
+    }
+
+    /// Share of the index cases that infected each number of people
+    pub fn offspring_distribution(&self) -> Vec<f64> {
+        let n = self.index_cases() as f64;
+        self.offspring.iter().map(|k| *k as f64 / n).collect()
+    }
+}
+
+impl AddAssign for SecondaryCases {
+    fn add_assign(&mut self, other: SecondaryCases) {
+        if self.offspring.len() < other.offspring.len() {
+            self.offspring.resize(other.offspring.len(), 0);
+        }
+        for (k, n) in other.offspring.iter().enumerate() {
+            self.offspring[k] += n;
+        }
+        self.household_contacts += other.household_contacts;
+        self.household_infections += other.household_infections;
+    }
+}
+
+/// Seeds each person on each day of the pattern n times, only letting the index case infect
+/// anyone, and collects who they infected
+pub fn secondary_cases(pattern: &PatternDesc, n: u64) -> Result<SecondaryCases, Error> {
+    pattern.validate()?;
+    let pattern_period = pattern
+        .cycles
+        .iter()
+        .map(|c| c.period)
+        .max()
+        .ok_or(Error::EmptyPattern)?;
+    let end = pattern.resolution.days(HORIZON);
+    let of = &pattern.households.of;
+
+    let mut cases = SecondaryCases::new();
+    for day in 0..pattern_period {
+        for index in 0..pattern.n_people {
+            let household: Vec<usize> = (0..pattern.n_people)
+                .filter(|p| *p != index && of[*p] == of[index])
+                .collect();
+            for _ in 0..n {
+                let run = run_single(pattern, index, day, true, None)?;
+                let infected = |p: &usize| *p != index && run.people[*p].was_sick(end);
+                cases.record(
+                    (0..pattern.n_people).filter(infected).count(),
+                    household.len(),
+                    household.iter().filter(|p| infected(p)).count(),
+                );
+            }
+        }
+    }
+    Ok(cases)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        CyclicPattern, DailyAction, Disease, DiseaseParams, Households, IsolationPolicy,
+        IsolationScope, Period, Resolution,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn offspring() {
+        let mut cases = SecondaryCases::new();
+        cases.record(0, 2, 0);
+        cases.record(3, 2, 1);
+        let mut more = SecondaryCases::new();
+        more.record(0, 1, 0);
+        more.record(1, 1, 1);
+        cases += more;
+
+        assert_eq!(cases.index_cases(), 4);
+        assert_eq!(cases.reproduction_number(), 1.);
+        assert_eq!(cases.secondary_attack_rate(), 2. / 6.);
+        assert_eq!(cases.offspring_distribution(), vec![0.5, 0.25, 0., 0.25]);
+    }
+
+    #[test]
+    fn first_generation() {
+        // a chain 0 - 1 - 2, everyone meeting daily and contagious well before symptoms: the
+        // middle person infects both ends, the ends only infect the middle
+        let mut actions = HashMap::new();
+        actions.insert(
+            0,
+            vec![DailyAction::Interact(0, 1), DailyAction::Interact(1, 2)],
+        );
+        let pattern = PatternDesc {
+            n_people: 3,
+            cycles: vec![CyclicPattern {
+                period: 1,
+                offset: 0,
+                actions,
+            }],
+            households: Households::new(vec![0, 0, 1]),
+            scope: IsolationScope::Individual,
+            resolution: Resolution::DAY,
+            adherence: Vec::new(),
+            symptoms: Vec::new(),
+            ages: Vec::new(),
+            disease: Disease::uniform(DiseaseParams {
+                incubation: Period::Fixed(5.),
+                ..DiseaseParams::default()
+            }),
+            isolation_policy: IsolationPolicy::default(),
+        };
+
+        let cases = secondary_cases(&pattern, 10).unwrap();
+        assert_eq!(cases.index_cases(), 30);
+        assert_eq!(cases.offspring_distribution(), vec![0., 2. / 3., 1. / 3.]);
+        assert_eq!(cases.reproduction_number(), 4. / 3.);
+        // households {0, 1} and {2}, only the first two have household members to infect
+        assert_eq!(cases.secondary_attack_rate(), 1.);
+    }
+}
@@ -0,0 +1,86 @@
+//! Results of finished scenarios kept on disk, so a long sweep can pick up where it stopped
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// A directory with one file per finished scenario, named after a hash of the scenario
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    dir: PathBuf,
+}
+
+impl Checkpoints {
+    /// Keeps checkpoints in the given directory, creating it if needed
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<Checkpoints> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Checkpoints { dir })
+    }
+
+    /// Hash of a scenario, given every parameter and the seed it runs with. This is FNV-1a,
+    /// which unlike the std hashers stays the same across builds
+    pub fn hash(scenario: &str) -> u64 {
+        scenario.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Identity of a scenario, its hash in hexadecimal
+    pub fn id(scenario: &str) -> String {
+        format!("{:016x}", Checkpoints::hash(scenario))
+    }
+
+    /// File holding the results of a scenario
+    pub fn path(&self, scenario: &str) -> PathBuf {
+        self.dir.join(format!("{}.csv", Checkpoints::id(scenario)))
+    }
+
+    /// Has this scenario already been run to completion?
+    pub fn is_done(&self, scenario: &str) -> bool {
+        self.path(scenario).is_file()
+    }
+
+    /// Saves the results of a scenario. They're written aside first, so a sweep dying half way
+    /// through never leaves a scenario looking done
+    pub fn save(&self, scenario: &str, results: &str) -> io::Result<()> {
+        let path = self.path(scenario);
+        let partial = path.with_extension("partial");
+        fs::write(&partial, results)?;
+        fs::rename(&partial, &path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn resume() {
+        let dir = env::temp_dir().join(format!("rustagious-checkpoints-{}", process::id()));
+        let checkpoints = Checkpoints::new(&dir).unwrap();
+
+        // same parameters, same id, whatever the build
+        assert_eq!(Checkpoints::id(""), "cbf29ce484222325");
+        assert_eq!(Checkpoints::id("a"), "af63dc4c8601ec8c");
+        let scenario = "a=7 ac=0 c=7 ca=0 offset=0 a_test=true n=10 seed=1";
+        let other = "a=7 ac=0 c=7 ca=0 offset=0 a_test=true n=10 seed=2";
+        assert_ne!(Checkpoints::id(scenario), Checkpoints::id(other));
+
+        assert!(!checkpoints.is_done(scenario));
+        checkpoints.save(scenario, "1, 2, 3\n").unwrap();
+        assert!(checkpoints.is_done(scenario));
+        assert!(!checkpoints.is_done(other));
+        assert_eq!(
+            fs::read_to_string(checkpoints.path(scenario)).unwrap(),
+            "1, 2, 3\n"
+        );
+
+        // picking up again from the same directory
+        let resumed = Checkpoints::new(&dir).unwrap();
+        assert!(resumed.is_done(scenario));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! When and how people find out they are infected
+
+use crate::{Person, Time};
+
+/// How someone found out they were infected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DetectionRoute {
+    /// Noticing their symptoms, without getting tested for them
+    Symptoms,
+
+    /// Getting tested because of symptoms, their own or those of a cold, found out either by
+    /// noticing their own symptoms or from the positive result
+    SymptomTest,
+
+    /// A positive scheduled test, pools and their reflex tests included
+    ScheduledTest,
+}
+
+impl DetectionRoute {
+    /// Short name, for reporting
+    pub fn name(&self) -> &'static str {
+        match self {
+            DetectionRoute::Symptoms => "symptoms",
+            DetectionRoute::SymptomTest => "symptom_test",
+            DetectionRoute::ScheduledTest => "scheduled_test",
+        }
+    }
+}
+
+/// The day someone found out they were infected, and how
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    /// Day the result came back or the symptoms were noticed
+    pub day: Time,
+    /// What gave the infection away
+    pub route: DetectionRoute,
+}
+
+impl Person {
+    /// Day this person got infected, if they ever did
+    pub fn infection_date(&self) -> Option<Time> {
+        self.infection.map(|i| i.date)
+    }
+
+    /// First time this person found out they were infected, as known on the given date. Ties go
+    /// to symptoms, then to tests taken because of symptoms
+    pub fn detection(&self, date: Time) -> Option<Detection> {
+        self.detections(date).into_iter().next()
+    }
+
+    /// Every time this person found out they were infected, as known on the given date, in the
+    /// order `detection` picks them
+    pub(crate) fn detections(&self, date: Time) -> Vec<Detection> {
+        let mut detections: Vec<Detection> = self
+            .tests
+            .iter()
+            .filter(|t| t.positive && t.reported && t.result_date <= date)
+            .map(|t| Detection {
+                day: t.result_date,
+                route: if t.prompted {
+                    DetectionRoute::SymptomTest
+                } else {
+                    DetectionRoute::ScheduledTest
+                },
+            })
+            .collect();
+        detections.extend(
+            self.infection
+                .and_then(|i| i.noticed_date)
+                .filter(|s| *s <= date)
+                .map(|day| Detection {
+                    day,
+                    route: if self.tests.iter().any(|t| t.prompted && t.date == day) {
+                        DetectionRoute::SymptomTest
+                    } else {
+                        DetectionRoute::Symptoms
+                    },
+                }),
+        );
+        detections.sort_unstable_by_key(|d| (d.day, d.route));
+        detections
+    }
+}
+
+/// First time anyone among the people found out they were infected, as known on the given date,
+/// ties going the same way as for a single person
+pub fn first_detection(people: &[Person], date: Time) -> Option<Detection> {
+    people
+        .iter()
+        .filter_map(|p| p.detection(date))
+        .min_by_key(|d| (d.day, d.route))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        run_outbreak, CyclicPattern, DailyAction, PatternDesc, SymptomModel, TestKind, HORIZON,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn detection_route() {
+        for _ in 0..1_000 {
+            let mut me = Person::new();
+            assert_eq!(me.infection_date(), None);
+            me.expose(100);
+            assert_eq!(me.infection_date(), Some(100));
+
+            let infection = me.get_infection().unwrap();
+            let t = infection.testable_date;
+            me.test(t, 1);
+            let detection = me.detection(1_000).unwrap();
+            assert_eq!(Some(detection.day), me.isolation_start(1_000));
+            match infection.noticed_date {
+                Some(s) if s <= t + 1 => {
+                    assert_eq!(
+                        detection,
+                        Detection {
+                            day: s,
+                            route: DetectionRoute::Symptoms
+                        }
+                    )
+                }
+                _ => assert_eq!(
+                    detection,
+                    Detection {
+                        day: t + 1,
+                        route: DetectionRoute::ScheduledTest
+                    }
+                ),
+            }
+
+            // before the result comes back, only symptoms give the infection away
+            let early = me.detection(t);
+            assert!(early.is_none_or(|d| d.route == DetectionRoute::Symptoms));
+        }
+    }
+
+    #[test]
+    fn symptom_tests() {
+        // a cold every few days, each one tested, while the infection's own symptoms get ignored
+        let symptoms = SymptomModel {
+            ignore: 1.,
+            background_rate: 1.,
+            test: Some(TestKind::PCR),
+            ..SymptomModel::default()
+        };
+        for _ in 0..100 {
+            let mut people = [
+                Person::new().with_symptoms(symptoms).unwrap(),
+                Person::new(),
+            ];
+            people[0].expose(100);
+            for day in 100..130 {
+                for p in &mut people {
+                    p.update_isolation(day);
+                }
+            }
+
+            let detection = first_detection(&people, 130).unwrap();
+            assert_eq!(detection.route, DetectionRoute::SymptomTest);
+            assert_eq!(Some(detection), people[0].detection(130));
+        }
+        assert_eq!(first_detection(&[Person::new()], 130), None);
+    }
+
+    #[test]
+    fn own_symptom_tests() {
+        // no colds, only the infection's own symptoms prompt a test
+        let symptoms = SymptomModel {
+            test: Some(TestKind::PCR),
+            ..SymptomModel::default()
+        };
+        let mut noticed = 0;
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_symptoms(symptoms).unwrap();
+            me.expose(100);
+            for day in 100..130 {
+                me.update_isolation(day);
+            }
+            assert!(me.colds.is_empty());
+
+            match me.get_infection().unwrap().noticed_date {
+                Some(s) => {
+                    noticed += 1;
+                    assert_eq!(
+                        me.detection(HORIZON),
+                        Some(Detection {
+                            day: s,
+                            route: DetectionRoute::SymptomTest
+                        })
+                    );
+                }
+                None => assert_eq!(me.detection(HORIZON), None),
+            }
+        }
+        assert!(noticed > 0);
+    }
+
+    #[test]
+    fn symptom_test_outbreaks() {
+        let mut actions = HashMap::new();
+        actions.insert(0, vec![DailyAction::Interact(0, 1)]);
+        let tested = SymptomModel {
+            test: Some(TestKind::PCR),
+            ..SymptomModel::default()
+        };
+        let pattern = PatternDesc::new(2, vec![CyclicPattern::new(1, 0, actions)])
+            .with_symptoms(vec![tested; 2]);
+
+        // no scheduled tests, so every outbreak found out is through a test prompted by symptoms
+        let mut detected = 0;
+        for _ in 0..100 {
+            let outbreak = run_outbreak(&pattern, 0, 10).unwrap();
+            if let Some(route) = outbreak.detection_route {
+                assert_eq!(route, DetectionRoute::SymptomTest);
+                detected += 1;
+            }
+        }
+        assert!(detected > 0);
+    }
+}
@@ -0,0 +1,517 @@
+//! Disease parameters, and how they vary with age
+
+use crate::{rng, Error, Person, Resolution, Severity, Time, SYMPTOMATIC_MU, SYMPTOMATIC_SIGMA};
+use rand::Rng;
+use rand_distr::{Distribution, Gamma, LogNormal, Weibull};
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+/// Broad age groups the disease treats differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AgeGroup {
+    /// Under 18
+    Child,
+
+    /// 18 to 64
+    #[default]
+    Adult,
+
+    /// 65 and over
+    Senior,
+}
+
+/// Distribution of a period of time, in days
+#[derive(Debug, Clone, PartialEq)]
+pub enum Period {
+    /// Always the same number of days
+    Fixed(f64),
+
+    /// Log-normal distribution
+    LogNormal {
+        /// Mean of the log of the period
+        mu: f64,
+        /// Standard deviation of the log of the period
+        sigma: f64,
+    },
+
+    /// Gamma distribution
+    Gamma {
+        /// Shape parameter
+        shape: f64,
+        /// Scale parameter, in days
+        scale: f64,
+    },
+
+    /// Weibull distribution
+    Weibull {
+        /// Shape parameter
+        shape: f64,
+        /// Scale parameter, in days
+        scale: f64,
+    },
+
+    /// Discretized empirical distribution, the probability of each whole number of days
+    Empirical(Arc<[f64]>),
+}
+
+impl Period {
+    /// Default incubation period, from exposure to symptom onset
+    pub const INCUBATION: Period = Period::LogNormal {
+        mu: SYMPTOMATIC_MU,
+        sigma: SYMPTOMATIC_SIGMA,
+    };
+
+    /// Empirical distribution from the weight of each whole number of days, normalized to sum
+    /// to one
+    pub fn empirical(weights: Vec<f64>) -> Period {
+        let total: f64 = weights.iter().sum();
+        Period::Empirical(weights.iter().map(|w| w / total).collect())
+    }
+
+    /// Reads an empirical distribution, one `days weight` pair per line. Days missing from the
+    /// file have no weight, blank lines and lines starting with `#` are ignored
+    pub fn from_pmf<R: BufRead>(reader: R) -> io::Result<Period> {
+        let mut weights = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid probability on line {}: {}", i + 1, line),
+                )
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 2 {
+                return Err(invalid());
+            }
+            let day: usize = fields[0].parse().map_err(|_| invalid())?;
+            let weight: f64 = fields[1].parse().map_err(|_| invalid())?;
+            if weight.is_nan() || weight < 0. {
+                return Err(invalid());
+            }
+            if weights.len() <= day {
+                weights.resize(day + 1, 0.);
+            }
+            weights[day] += weight;
+        }
+
+        if weights.iter().sum::<f64>() <= 0. {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty distribution",
+            ));
+        }
+        Ok(Period::empirical(weights))
+    }
+
+    /// Draws a period, in days
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Result<f64, Error> {
+        let invalid = || Error::InvalidParameter(format!("{:?}", self));
+        let days = match self {
+            Period::Fixed(days) => *days,
+            Period::LogNormal { mu, sigma } => LogNormal::new(*mu, *sigma)
+                .map_err(|_| invalid())?
+                .sample(rng),
+            Period::Gamma { shape, scale } => Gamma::new(*shape, *scale)
+                .map_err(|_| invalid())?
+                .sample(rng),
+            Period::Weibull { shape, scale } => Weibull::new(*scale, *shape)
+                .map_err(|_| invalid())?
+                .sample(rng),
+            Period::Empirical(pmf) => {
+                if pmf.is_empty() || pmf.iter().any(|p| !(0. ..=1.).contains(p)) {
+                    return Err(invalid());
+                }
+                let mut u: f64 = rng.gen();
+                let mut days = pmf.len() - 1;
+                for (day, p) in pmf.iter().enumerate() {
+                    if u < *p {
+                        days = day;
+                        break;
+                    }
+                    u -= p;
+                }
+                days as f64
+            }
+        };
+
+        if days.is_finite() && days >= 0. {
+            Ok(days)
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Average period, in days
+    pub fn mean(&self) -> Result<f64, Error> {
+        let invalid = || Error::InvalidParameter(format!("{:?}", self));
+        let positive = |x: &f64| x.is_finite() && *x > 0.;
+        let days = match self {
+            Period::Fixed(days) => *days,
+            Period::LogNormal { mu, sigma } if sigma.is_finite() && *sigma >= 0. => {
+                (mu + sigma * sigma / 2.).exp()
+            }
+            Period::Gamma { shape, scale } if positive(shape) && positive(scale) => shape * scale,
+            Period::Weibull { shape, scale } if positive(shape) && positive(scale) => {
+                scale * gamma(1. + 1. / shape)
+            }
+            Period::Empirical(pmf)
+                if !pmf.is_empty() && pmf.iter().all(|p| (0. ..=1.).contains(p)) =>
+            {
+                pmf.iter().enumerate().map(|(day, p)| day as f64 * p).sum()
+            }
+            _ => return Err(invalid()),
+        };
+
+        if days.is_finite() && days >= 0. {
+            Ok(days)
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Draws a period, rounded to steps of the given resolution
+    pub(crate) fn sample_steps<R: Rng>(&self, r: Resolution, rng: &mut R) -> Result<Time, Error> {
+        let steps = (self.sample(rng)? * r.steps_per_day() as f64).round();
+        if steps < Time::MAX as f64 {
+            Ok(steps as Time)
+        } else {
+            Err(Error::TimeOverflow)
+        }
+    }
+}
+
+/// Gamma function for positive arguments, Lanczos approximation
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        return std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1. - x));
+    }
+
+    let x = x - 1.;
+    let t = x + G + 0.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.));
+    (2. * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * series
+}
+
+/// How the disease plays out for a single person
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiseaseParams {
+    /// Time from exposure to symptom onset, whether or not symptoms actually show. Onset never
+    /// comes before the end of the latent period, shorter draws are pushed back to it
+    pub incubation: Period,
+    /// Shortest time from exposure to being contagious, testable or symptomatic
+    pub latent: Period,
+    /// Time someone is contagious before symptom onset
+    pub presymptomatic: Period,
+    /// Time someone stays contagious after symptom onset
+    pub infectious: Period,
+    /// Time before symptom onset from which tests can detect the infection
+    pub detectable_before_onset: Period,
+    /// Time after symptom onset until which tests can detect the infection
+    pub detectable_after_onset: Period,
+    /// Probability of ever showing symptoms
+    pub symptomatic_fraction: f64,
+    /// Relative chance of catching the virus from a contagious contact
+    pub susceptibility: f64,
+    /// Relative chance of passing the virus on while contagious
+    pub infectiousness: f64,
+    /// Risks of severe outcomes, if they are modelled at all
+    pub severity: Option<Severity>,
+}
+
+impl Default for DiseaseParams {
+    fn default() -> Self {
+        DiseaseParams {
+            incubation: Period::INCUBATION,
+            latent: Period::Fixed(1.),
+            presymptomatic: Period::Fixed(2.),
+            infectious: Period::Fixed(10.),
+            detectable_before_onset: Period::Fixed(2.),
+            detectable_after_onset: Period::Fixed(10.),
+            symptomatic_fraction: 0.6,
+            susceptibility: 1.,
+            infectiousness: 1.,
+            severity: None,
+        }
+    }
+}
+
+impl DiseaseParams {
+    /// Checks every period has a finite, non-negative mean, and every probability, those of
+    /// severe outcomes included, and relative rate is in range
+    pub fn validate(&self) -> Result<(), Error> {
+        for period in &[
+            &self.incubation,
+            &self.latent,
+            &self.presymptomatic,
+            &self.infectious,
+            &self.detectable_before_onset,
+            &self.detectable_after_onset,
+        ] {
+            period.mean()?;
+        }
+        let fraction = self.symptomatic_fraction;
+        if !(0. ..=1.).contains(&fraction) {
+            return Err(Error::InvalidParameter(format!(
+                "symptomatic fraction of {}",
+                fraction
+            )));
+        }
+        for (name, rate) in &[
+            ("susceptibility", self.susceptibility),
+            ("infectiousness", self.infectiousness),
+        ] {
+            if !rate.is_finite() || *rate < 0. {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, rate)));
+            }
+        }
+        self.severity.map_or(Ok(()), |severity| severity.validate())
+    }
+}
+
+/// The disease parameters of every age group
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Disease {
+    child: DiseaseParams,
+    adult: DiseaseParams,
+    senior: DiseaseParams,
+}
+
+impl Disease {
+    /// The same parameters for everyone
+    pub fn uniform(params: DiseaseParams) -> Disease {
+        Disease {
+            child: params.clone(),
+            adult: params.clone(),
+            senior: params,
+        }
+    }
+
+    /// Children half as susceptible as adults and mostly without symptoms, seniors mostly
+    /// symptomatic and far more likely to end up in hospital
+    // rough figures from https://www.nature.com/articles/s41591-020-0962-9
+    pub fn age_structured() -> Disease {
+        let adult = DiseaseParams {
+            severity: Some(Severity::typical(0.03, 0.2, 0.05)),
+            ..DiseaseParams::default()
+        };
+        Disease {
+            child: DiseaseParams {
+                symptomatic_fraction: 0.25,
+                susceptibility: 0.5,
+                severity: Some(Severity::typical(0.005, 0.1, 0.01)),
+                ..adult.clone()
+            },
+            adult: adult.clone(),
+            senior: DiseaseParams {
+                symptomatic_fraction: 0.7,
+                severity: Some(Severity::typical(0.2, 0.3, 0.25)),
+                ..adult
+            },
+        }
+    }
+
+    /// Sets the parameters of an age group
+    pub fn with(mut self, age: AgeGroup, params: DiseaseParams) -> Disease {
+        match age {
+            AgeGroup::Child => self.child = params,
+            AgeGroup::Adult => self.adult = params,
+            AgeGroup::Senior => self.senior = params,
+        }
+        self
+    }
+
+    /// Parameters of an age group
+    pub fn params(&self, age: AgeGroup) -> &DiseaseParams {
+        match age {
+            AgeGroup::Child => &self.child,
+            AgeGroup::Adult => &self.adult,
+            AgeGroup::Senior => &self.senior,
+        }
+    }
+}
+
+impl Person {
+    /// Sets this person's age group, the disease then following that group's parameters. Fails
+    /// when those parameters are out of range
+    pub fn with_age(mut self, age: AgeGroup, disease: &Disease) -> Result<Person, Error> {
+        let params = disease.params(age);
+        params.validate()?;
+        self.age = age;
+        self.params = params.clone();
+        Ok(self)
+    }
+
+    /// Age group of this person
+    pub fn age(&self) -> AgeGroup {
+        self.age
+    }
+}
+
+/// Does an interaction passing the virus with the given probability infect `to`, accounting for
+/// how infectious `from` is and how susceptible `to` is?
+pub(crate) fn transmits(from: &Person, to: &Person, probability: f64) -> bool {
+    let p = probability * from.params.infectiousness * to.params.susceptibility;
+    p >= 1. || (p > 0. && rng().gen_bool(p))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn age_groups() {
+        let disease = Disease::age_structured();
+        assert_eq!(disease.params(AgeGroup::Adult).symptomatic_fraction, 0.6);
+        assert_eq!(disease.params(AgeGroup::Child).susceptibility, 0.5);
+
+        let immune = DiseaseParams {
+            susceptibility: 0.,
+            ..DiseaseParams::default()
+        };
+        let disease = disease.with(AgeGroup::Senior, immune.clone());
+        assert_eq!(disease.params(AgeGroup::Senior), &immune);
+
+        // a contagious adult never infects an immune senior
+        let mut adult = Person::new();
+        let mut senior = Person::new().with_age(AgeGroup::Senior, &disease).unwrap();
+        assert_eq!(senior.age(), AgeGroup::Senior);
+        adult.expose(100);
+        let t = adult.get_infection().unwrap().contagious_date;
+        for day in t..t + 5 {
+            senior.interact(day, &mut adult);
+        }
+        assert!(senior.get_infection().is_none());
+    }
+
+    #[test]
+    fn infection_timeline() {
+        // contagious from symptom onset only, a gamma infectious period, tests detecting the
+        // virus for weeks after
+        let params = DiseaseParams {
+            presymptomatic: Period::Fixed(0.),
+            infectious: Period::Gamma {
+                shape: 4.,
+                scale: 2.,
+            },
+            detectable_before_onset: Period::Fixed(3.),
+            detectable_after_onset: Period::Fixed(30.),
+            ..DiseaseParams::default()
+        };
+        let disease = Disease::uniform(params);
+
+        let mut infectious = HashSet::new();
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_age(AgeGroup::Adult, &disease).unwrap();
+            me.expose(100);
+            let infection = me.get_infection().unwrap();
+            assert!(infection.contagious_date >= 101);
+            assert!(infection.testable_date <= infection.contagious_date);
+            assert!(me.is_testable(infection.testable_end));
+            assert!(!me.is_testable(infection.testable_end + 1));
+            assert!(!me.is_contagious(infection.recovery_date + 1));
+            infectious.insert(infection.recovery_date - infection.contagious_date);
+        }
+        assert!(infectious.len() > 5);
+    }
+
+    #[test]
+    fn period_means() {
+        let close = |period: Period, mean: f64| (period.mean().unwrap() - mean).abs() < 1e-6;
+        assert!(close(Period::Fixed(2.), 2.));
+        assert!(close(Period::INCUBATION, (1.63f64 + 0.125).exp()));
+        assert!(close(
+            Period::Gamma {
+                shape: 4.,
+                scale: 2.
+            },
+            8.
+        ));
+        // shape 1 is an exponential, shape 2 has a mean of scale * sqrt(pi) / 2
+        assert!(close(
+            Period::Weibull {
+                shape: 1.,
+                scale: 3.
+            },
+            3.
+        ));
+        assert!(close(
+            Period::Weibull {
+                shape: 2.,
+                scale: 2.
+            },
+            std::f64::consts::PI.sqrt()
+        ));
+        assert!(close(Period::empirical(vec![0., 1., 1., 2.]), 2.25));
+        assert!(Period::Gamma {
+            shape: -1.,
+            scale: 2.
+        }
+        .mean()
+        .is_err());
+        assert!(Period::empirical(Vec::new()).mean().is_err());
+    }
+
+    #[test]
+    fn invalid_periods() {
+        assert!(DiseaseParams::default().validate().is_ok());
+        let invalid = [
+            DiseaseParams {
+                latent: Period::Fixed(f64::NAN),
+                ..DiseaseParams::default()
+            },
+            DiseaseParams {
+                infectious: Period::Gamma {
+                    shape: 0.,
+                    scale: 2.,
+                },
+                ..DiseaseParams::default()
+            },
+            DiseaseParams {
+                detectable_after_onset: Period::empirical(Vec::new()),
+                ..DiseaseParams::default()
+            },
+        ];
+        for params in &invalid {
+            assert!(matches!(params.validate(), Err(Error::InvalidParameter(_))));
+
+            // caught when the person is set up, not on exposure
+            let disease = Disease::uniform(params.clone());
+            assert!(Person::new().with_age(AgeGroup::Adult, &disease).is_err());
+        }
+    }
+
+    #[test]
+    fn symptomatic_fraction() {
+        let disease = Disease::age_structured();
+        let symptomatic = (0..10_000)
+            .filter(|_| {
+                let mut child = Person::new().with_age(AgeGroup::Child, &disease).unwrap();
+                child.expose(100);
+                child.get_infection().unwrap().symptomatic_date.is_some()
+            })
+            .count();
+        assert!((2_200..2_800).contains(&symptomatic), "{}", symptomatic);
+    }
+}
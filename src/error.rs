@@ -0,0 +1,39 @@
+//! Errors returned by the library
+
+use std::fmt;
+
+/// Everything that can go wrong building or running a model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A parameter is outside of its valid range
+    InvalidParameter(String),
+
+    /// A person that is not part of the population
+    UnknownPerson {
+        /// Index of the person
+        person: usize,
+        /// Number of people in the population
+        n_people: usize,
+    },
+
+    /// A pattern without any cycle
+    EmptyPattern,
+
+    /// A date went past the last time that can be represented
+    TimeOverflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidParameter(reason) => write!(f, "invalid parameter: {}", reason),
+            Error::UnknownPerson { person, n_people } => {
+                write!(f, "unknown person {} among {} people", person, n_people)
+            }
+            Error::EmptyPattern => write!(f, "empty pattern"),
+            Error::TimeOverflow => write!(f, "time overflow"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
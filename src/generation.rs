@@ -0,0 +1,190 @@
+//! Who infected whom: generations of infections and the serial intervals between them
+
+use crate::{Error, Infection, Person, Time};
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+impl Person {
+    /// Exposes this person to the virus passed on by someone with the given infection, or by
+    /// an outside source if there is none. `infector` is the index of that someone, if known
+    pub(crate) fn try_expose_from(
+        &mut self,
+        date: Time,
+        source: Option<Infection>,
+        infector: Option<usize>,
+    ) -> Result<(), Error> {
+        if self.infection.is_some() {
+            return Ok(());
+        }
+
+        self.try_expose(date)?;
+        if let (Some(infection), Some(source)) = (self.infection.as_mut(), source) {
+            infection.generation = source.generation + 1;
+            infection.infector = infector;
+            infection.infector_onset = source.symptomatic_date;
+        }
+        Ok(())
+    }
+
+    /// Same as `try_expose_from`, panicking like `expose`
+    pub(crate) fn expose_from(
+        &mut self,
+        date: Time,
+        source: Option<Infection>,
+        infector: Option<usize>,
+    ) {
+        if let Err(e) = self.try_expose_from(date, source, infector) {
+            panic!("could not expose on {}: {}", date, e);
+        }
+    }
+
+    /// Number of links between this person and the seed of the outbreak, 0 for the seed itself,
+    /// nothing if they were never infected
+    pub fn generation(&self) -> Option<usize> {
+        self.infection.map(|i| i.generation)
+    }
+
+    /// Index of whoever passed the virus on to this person, unless it came from outside or
+    /// they weren't given one
+    pub fn infector(&self) -> Option<usize> {
+        self.infection.and_then(|i| i.infector)
+    }
+
+    /// Time from the symptom onset of whoever infected this person to their own, which can be
+    /// negative. Nothing unless both showed symptoms
+    pub fn serial_interval(&self) -> Option<i64> {
+        let infection = self.infection?;
+        Some(infection.symptomatic_date? as i64 - infection.infector_onset? as i64)
+    }
+}
+
+/// Infections in each generation and serial intervals, over a number of trials
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Generations {
+    trials: u64,
+    infections: Vec<u64>,
+    serial_intervals: BTreeMap<i64, u64>,
+}
+
+impl Generations {
+    /// No trial yet
+    pub fn new() -> Generations {
+        Generations::default()
+    }
+
+    /// Adds a trial, given everyone at its end
+    pub fn record(&mut self, people: &[Person]) {
+        self.trials += 1;
+        for p in people {
+            if let Some(generation) = p.generation() {
+                if self.infections.len() <= generation {
+                    self.infections.resize(generation + 1, 0);
+                }
+                self.infections[generation] += 1;
+            }
+            if let Some(interval) = p.serial_interval() {
+                *self.serial_intervals.entry(interval).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Number of trials recorded
+    pub fn trials(&self) -> u64 {
+        self.trials
+    }
+
+    /// Mean number of infections per trial in the given generation, the seed being generation 0
+    pub fn mean_infections(&self, generation: usize) -> f64 {
+        let infections = self.infections.get(generation).copied().unwrap_or(0);
+        infections as f64 / self.trials as f64
+    }
+
+    /// Last generation anyone was infected in, nothing without any infection
+    pub fn last_generation(&self) -> Option<usize> {
+        self.infections.len().checked_sub(1)
+    }
+
+    /// How often each serial interval was seen, in steps of the people's resolution
+    pub fn serial_intervals(&self) -> &BTreeMap<i64, u64> {
+        &self.serial_intervals
+    }
+
+    /// Mean serial interval, nothing if no infector and infectee both showed symptoms
+    pub fn mean_serial_interval(&self) -> Option<f64> {
+        let n: u64 = self.serial_intervals.values().sum();
+        let total: i64 = self
+            .serial_intervals
+            .iter()
+            .map(|(interval, k)| interval * *k as i64)
+            .sum();
+        if n > 0 {
+            Some(total as f64 / n as f64)
+        } else {
+            None
+        }
+    }
+}
+
+impl AddAssign for Generations {
+    fn add_assign(&mut self, other: Generations) {
+        self.trials += other.trials;
+        if self.infections.len() < other.infections.len() {
+            self.infections.resize(other.infections.len(), 0);
+        }
+        for (generation, n) in other.infections.iter().enumerate() {
+            self.infections[generation] += n;
+        }
+        for (interval, n) in other.serial_intervals {
+            *self.serial_intervals.entry(interval).or_insert(0) += n;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transmission_chain() {
+        let mut generations = Generations::new();
+        for _ in 0..100 {
+            // a infects b, who infects c
+            let mut people = [Person::new(), Person::new(), Person::new()];
+            people[0].expose(100);
+            let [a, b, c] = &mut people;
+            for day in 100..200 {
+                b.interact(day, a);
+                c.interact(day, b);
+            }
+            assert_eq!(a.generation(), Some(0));
+            assert_eq!(a.serial_interval(), None);
+            if b.was_sick(200) {
+                assert_eq!(b.generation(), Some(1));
+            }
+            if let (Some(s_b), Some(s_c)) = (b.get_infection(), c.get_infection()) {
+                assert_eq!(c.generation(), Some(2));
+                if let (Some(onset_b), Some(onset_c)) = (s_b.symptomatic_date, s_c.symptomatic_date)
+                {
+                    assert_eq!(c.serial_interval(), Some(onset_c as i64 - onset_b as i64));
+                }
+            }
+            generations.record(&people);
+        }
+
+        assert_eq!(generations.trials(), 100);
+        assert_eq!(generations.mean_infections(0), 1.);
+        assert!(generations.mean_infections(2) > 0.);
+        assert_eq!(generations.mean_infections(3), 0.);
+        assert_eq!(generations.last_generation(), Some(2));
+        assert!(generations.mean_serial_interval().is_some());
+
+        let mut doubled = generations.clone();
+        doubled += generations.clone();
+        assert_eq!(doubled.trials(), 200);
+        assert_eq!(doubled.mean_infections(1), generations.mean_infections(1));
+        assert_eq!(
+            doubled.mean_serial_interval(),
+            generations.mean_serial_interval()
+        );
+    }
+}
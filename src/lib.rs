@@ -3,13 +3,101 @@
 
 //! A crate to model contagion
 
+use disease::transmits;
 use rand::distributions::{Bernoulli, Distribution};
-use rand_distr::LogNormal;
+use rand::Rng;
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+mod adherence;
+mod agenda;
+mod branching;
+mod checkpoint;
+mod detection;
+mod disease;
+mod error;
+mod generation;
+mod network;
+mod optimize;
+mod random;
+mod school;
+mod seir;
+mod severity;
+mod symptoms;
+mod testing;
+mod trace;
+
+pub use adherence::Adherence;
+pub use agenda::Agenda;
+pub use branching::{secondary_cases, SecondaryCases};
+pub use checkpoint::Checkpoints;
+pub use detection::{first_detection, Detection, DetectionRoute};
+pub use disease::{AgeGroup, Disease, DiseaseParams, Period};
+pub use error::Error;
+pub use generation::Generations;
+pub use network::{Contact, ContactNetwork, ContactSchedule, Outbreak};
+pub use optimize::{optimize, pareto_front, rank_testing, Constraints, Estimate, Schedule};
+pub use random::{reseed, rng, stream_seed, SimRng};
+pub use school::{School, SchoolOutbreak, SchoolWeeks};
+pub use seir::{Seir, SeirState};
+pub use severity::{SevereOutcome, SevereRisk, Severity};
+pub use symptoms::SymptomModel;
+pub use testing::{pool_test, ScheduledTest, TestKind, TestingSchedule};
+pub use trace::{Event, Trace};
 
 type Time = u64;
 
+/// Last day simulated by the pattern runner
+const HORIZON: Time = 400;
+
+/// How finely time is divided: every `Time` counts steps, and there are `steps_per_day` of them
+/// in a day. Dates are always given in steps, while durations such as test delays or isolation
+/// lengths stay in days. The default of one step per day keeps the whole-day behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    steps_per_day: u64,
+}
+
+impl Resolution {
+    /// Whole days
+    pub const DAY: Resolution = Resolution { steps_per_day: 1 };
+
+    /// Mornings and evenings
+    pub const HALF_DAY: Resolution = Resolution { steps_per_day: 2 };
+
+    /// Hours
+    pub const HOUR: Resolution = Resolution { steps_per_day: 24 };
+
+    /// Splits days into the given number of steps, failing on 0
+    pub fn new(steps_per_day: u64) -> Result<Resolution, Error> {
+        if steps_per_day == 0 {
+            return Err(Error::InvalidParameter("0 steps per day".to_string()));
+        }
+        Ok(Resolution { steps_per_day })
+    }
+
+    /// Number of steps in a day
+    pub fn steps_per_day(self) -> u64 {
+        self.steps_per_day
+    }
+
+    /// Number of steps in the given number of days
+    pub fn days(self, days: Time) -> Time {
+        days * self.steps_per_day
+    }
+
+    /// Day containing the given step, the coarse day-based view of a time
+    pub fn day(self, time: Time) -> Time {
+        time / self.steps_per_day
+    }
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Resolution::DAY
+    }
+}
+
 // old
 //const SYMPTOMATIC_MU: f64 = 1.621;
 //const SYMPTOMATIC_SIGMA: f64 = 0.418;
@@ -23,8 +111,73 @@ const SYMPTOMATIC_SIGMA: f64 = 0.5;
 pub struct Person {
     // /// Person's name for easy referencing
     //pub name: String,
+    id: Option<usize>,
     infection: Option<Infection>,
-    tested_positive: Option<Time>,
+    tests: Vec<TestResult>,
+    isolation_policy: IsolationPolicy,
+    resolution: Resolution,
+    symptoms: SymptomModel,
+    next_cold: Option<Time>,
+    colds: Vec<Time>,
+    adherence: Adherence,
+    age: AgeGroup,
+    params: DiseaseParams,
+    vaccinated: bool,
+}
+
+/// Rules deciding when someone who started isolating can stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationPolicy {
+    /// Never stop isolating
+    #[default]
+    Indefinite,
+
+    /// Isolate for a fixed number of days
+    Fixed(Time),
+
+    /// Isolate for at least n days, and until symptoms are gone
+    SymptomBased(Time),
+
+    /// Isolate until `negatives` tests come back negative, retesting every `interval` days
+    TestBased {
+        /// Number of negative results needed to stop isolating
+        negatives: usize,
+        /// Days between two release tests
+        interval: Time,
+        /// Delay before the release test results come back
+        delay: Time,
+    },
+}
+
+/// How far a single person's isolation extends to the people they live with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationScope {
+    /// Only the person themselves isolates
+    #[default]
+    Individual,
+
+    /// Their whole household isolates with them
+    Household,
+
+    /// Their household and every household linked to it isolate
+    LinkedHouseholds,
+}
+
+/// Which household each person lives in, and which households share members
+#[derive(Debug, Clone, Default)]
+pub struct Households {
+    of: Vec<usize>,
+    links: Vec<(usize, usize)>,
+}
+
+/// A test that was taken, and its result
+#[derive(Debug, Clone, Copy)]
+struct TestResult {
+    date: Time,
+    result_date: Time,
+    positive: bool,
+    reported: bool,
+    prompted: bool,
 }
 
 /// Infection data
@@ -33,10 +186,18 @@ pub struct Infection {
     date: Time,
 
     testable_date: Time,
+    testable_end: Time,
     contagious_date: Time,
     recovery_date: Time,
 
     symptomatic_date: Option<Time>,
+    noticed_date: Option<Time>,
+    severe: Option<SevereOutcome>,
+
+    // Links along the transmission chain, 0 for a seed
+    generation: usize,
+    infector: Option<usize>,
+    infector_onset: Option<Time>,
     // Infection's original source
     //pub source: String,
 }
@@ -47,12 +208,23 @@ impl Default for Person {
     }
 }
 
+#[allow(clippy::needless_arbitrary_self_type)]
 impl Person {
     /// Creates a new person
     pub fn new() -> Person {
         Person {
+            id: None,
             infection: None,
-            tested_positive: None,
+            tests: Vec::new(),
+            isolation_policy: IsolationPolicy::default(),
+            resolution: Resolution::default(),
+            symptoms: SymptomModel::default(),
+            next_cold: None,
+            colds: Vec::new(),
+            adherence: Adherence::default(),
+            age: AgeGroup::default(),
+            params: DiseaseParams::default(),
+            vaccinated: false,
         }
     }
 
@@ -60,41 +232,122 @@ impl Person {
     pub fn new_str(_name: String) -> Person {
         Person {
             //name,
+            id: None,
             infection: None,
-            tested_positive: None,
+            tests: Vec::new(),
+            isolation_policy: IsolationPolicy::default(),
+            resolution: Resolution::default(),
+            symptoms: SymptomModel::default(),
+            next_cold: None,
+            colds: Vec::new(),
+            adherence: Adherence::default(),
+            age: AgeGroup::default(),
+            params: DiseaseParams::default(),
+            vaccinated: false,
         }
     }
 
+    /// Gives this person their index among everyone simulated, naming them as the source of the
+    /// infections they pass on
+    pub fn with_id(mut self, id: usize) -> Person {
+        self.id = Some(id);
+        self
+    }
+
+    /// Switches this person to the given isolation policy
+    pub fn with_isolation_policy(mut self, isolation_policy: IsolationPolicy) -> Person {
+        self.isolation_policy = isolation_policy;
+        self
+    }
+
+    /// Switches this person to a finer time resolution, every date they are given is then
+    /// counted in steps of that resolution rather than in days
+    pub fn at_resolution(mut self, resolution: Resolution) -> Person {
+        self.resolution = resolution;
+        self
+    }
+
     /// Exposes a person to a source on a given date
+    ///
+    /// # Panics
+    ///
+    /// If this person's disease parameters are invalid, see `try_expose`
     pub fn expose(self: &mut Self, date: Time /*source: String*/) {
+        if let Err(e) = self.try_expose(date) {
+            panic!("could not expose on {}: {}", date, e);
+        }
+    }
+
+    /// Exposes a person to a source on a given date, failing on invalid disease parameters or
+    /// when the infection would last past the last representable time
+    pub fn try_expose(&mut self, date: Time) -> Result<(), Error> {
         // already infected, let's not do this again...
         if self.infection.is_some() {
-            return;
+            return Ok(());
         }
 
-        let mut rng = rand::thread_rng();
-
-        // Symptomatic date, everything is computed in reference to that
-        // Contagious period is 2, at least one day of incubation
-        let log_normal = LogNormal::new(SYMPTOMATIC_MU, SYMPTOMATIC_SIGMA).unwrap();
-        let symptomatic_date: Time = date + log_normal.sample(&mut rng).round() as Time;
-        let testable_date = max(date + 1, symptomatic_date - 2);
-        let contagious_date = max(date + 1, symptomatic_date - 2);
-        let recovery_date = symptomatic_date + 10;
+        let mut rng = rng();
+        let params = &self.params;
+        params.validate()?;
+        let after = |date: Time, steps: Time| date.checked_add(steps).ok_or(Error::TimeOverflow);
+
+        // Symptomatic date, everything is computed in reference to that, each period being drawn
+        // independently. Nobody is contagious or testable before the latent period is over, which
+        // also covers onsets too early for the periods before them to fit. Symptoms never start
+        // before the latent period is over either, shorter incubations are stretched to its end
+        let r = self.resolution;
+        let latent_end = after(date, params.latent.sample_steps(r, &mut rng)?)?;
+        let symptomatic_date = max(
+            latent_end,
+            after(date, params.incubation.sample_steps(r, &mut rng)?)?,
+        );
+        let presymptomatic = params.presymptomatic.sample_steps(r, &mut rng)?;
+        let contagious_date = max(latent_end, symptomatic_date.saturating_sub(presymptomatic));
+        let recovery_date = after(
+            symptomatic_date,
+            params.infectious.sample_steps(r, &mut rng)?,
+        )?;
+        let detectable = params.detectable_before_onset.sample_steps(r, &mut rng)?;
+        let testable_date = max(latent_end, symptomatic_date.saturating_sub(detectable));
+        let testable_end = after(
+            symptomatic_date,
+            params.detectable_after_onset.sample_steps(r, &mut rng)?,
+        )?;
 
         // Do we show symptoms
-        let d = Bernoulli::new(0.6).unwrap();
+        let d = Bernoulli::new(params.symptomatic_fraction)
+            .map_err(|e| Error::InvalidParameter(e.to_string()))?;
         let v = d.sample(&mut rng);
         let symptomatic_date = if v { Some(symptomatic_date) } else { None };
+        let noticed_date = match symptomatic_date {
+            Some(s) => self.symptoms.notice(s, r, &mut rng)?,
+            None => None,
+        };
+        let severe = params.severity.map(|severity| match symptomatic_date {
+            Some(s) => severity.outcome(s, self.vaccinated, r, &mut rng),
+            None => SevereOutcome::default(),
+        });
 
         self.infection = Some(Infection {
             date,
             testable_date,
+            testable_end,
             contagious_date,
             symptomatic_date,
+            noticed_date,
+            severe,
             recovery_date,
+            generation: 0,
+            infector: None,
+            infector_onset: None,
             //source,
         });
+
+        // acting on symptoms may mean getting tested
+        if let (Some(noticed), Some(kind)) = (noticed_date, self.symptoms.test) {
+            self.test_for_symptoms(noticed, kind);
+        }
+        Ok(())
     }
 
     /// True if the infection is done/has never happened
@@ -106,26 +359,71 @@ impl Person {
         }
     }
 
-    /// Runs a test on a person
-    pub fn test(self: &mut Self, date: Time, delay: Time) {
+    /// Would a perfect test taken on this date come back positive?
+    pub fn is_testable(&self, date: Time) -> bool {
         if let Some(infection) = &self.infection {
-            if infection.testable_date <= date {
-                self.tested_positive = Some(date + delay);
-            }
+            infection.testable_date <= date && date <= infection.testable_end
+        } else {
+            false
         }
     }
 
+    /// Runs a test on a person, the result coming back `delay` days later
+    pub fn test(self: &mut Self, date: Time, delay: Time) {
+        let positive = self.is_testable(date);
+        self.tests.push(TestResult {
+            date,
+            result_date: date + self.resolution.days(delay),
+            positive,
+            reported: self.reports(positive),
+            prompted: false,
+        });
+    }
+
+    /// Runs a test of the given kind on a person, which can miss a testable infection. Fails when
+    /// the kind's sensitivity isn't a probability
+    pub fn test_with(&mut self, date: Time, kind: TestKind) -> Result<(), Error> {
+        kind.validate()?;
+        self.take_test(date, kind);
+        Ok(())
+    }
+
+    /// Same as `test_with`, for kinds already validated
+    pub(crate) fn take_test(&mut self, date: Time, kind: TestKind) {
+        let positive = self.is_testable(date) && rng().gen_bool(kind.sensitivity);
+        self.tests.push(TestResult {
+            date,
+            result_date: date + self.resolution.days(kind.delay),
+            positive,
+            reported: self.reports(positive),
+            prompted: false,
+        });
+    }
+
     /// Interacts two people
     pub fn interact(self: &mut Self, date: Time, other: &mut Self) {
-        if other.is_contagious(date) {
-            self.expose(
+        if other.is_contagious(date) && transmits(other, self, 1.) {
+            self.expose_from(
                 date,
+                other.infection,
+                other.id,
                 //other.get_infection().as_ref().unwrap().source.to_string(),
             );
         }
 
-        if self.is_contagious(date) {
-            other.expose(date); //, self.infection.as_ref().unwrap().source.to_string());
+        if self.is_contagious(date) && transmits(self, other, 1.) {
+            other.expose_from(date, self.infection, self.id); //, self.infection.as_ref().unwrap().source.to_string());
+        }
+    }
+
+    /// Interacts two people, the virus only getting through with the given probability
+    pub fn interact_with_probability(&mut self, date: Time, other: &mut Self, probability: f64) {
+        if other.is_contagious(date) && transmits(other, self, probability) {
+            self.expose_from(date, other.infection, other.id);
+        }
+
+        if self.is_contagious(date) && transmits(self, other, probability) {
+            other.expose_from(date, self.infection, self.id);
         }
     }
 
@@ -139,18 +437,83 @@ impl Person {
 
     /// Is this person in a state where they should be isolating?
     pub fn is_isolating(self: &Self, date: Time) -> bool {
-        if let Some(tested_date) = self.tested_positive {
-            if tested_date <= date {
-                return true;
+        if self.is_isolating_for_cold(date) {
+            true
+        } else if let Some(start) = self.isolation_start(date) {
+            match self.isolation_end(start, date) {
+                Some(end) => date < end,
+                None => true,
             }
+        } else {
+            false
         }
-        if let Some(infection) = &self.infection {
-            if let Some(symptomatic_date) = infection.symptomatic_date {
-                //println!("Symptomatic and isolating!");
-                return symptomatic_date <= date;
+    }
+
+    /// Day this person started their latest isolation, as known on the given date. Finding out
+    /// again while isolating changes nothing, but doing so after release starts a new isolation
+    pub fn isolation_start(&self, date: Time) -> Option<Time> {
+        let mut start: Option<Time> = None;
+        for detection in self.detections(date) {
+            let isolating = start.is_some_and(|s| {
+                self.isolation_end(s, date)
+                    .is_none_or(|end| detection.day < end)
+            });
+            if !isolating {
+                start = Some(detection.day);
+            }
+        }
+        start
+    }
+
+    /// First day this person is allowed out of an isolation started on `start`, as known on the
+    /// given date
+    fn isolation_end(&self, start: Time, date: Time) -> Option<Time> {
+        match self.isolation_policy {
+            IsolationPolicy::Indefinite => None,
+            IsolationPolicy::Fixed(days) => Some(start + self.resolution.days(days)),
+            IsolationPolicy::SymptomBased(days) => {
+                let symptoms_end = self
+                    .infection
+                    .filter(|i| i.symptomatic_date.is_some())
+                    .map(|i| i.recovery_date + 1);
+                Some(max(
+                    start + self.resolution.days(days),
+                    symptoms_end.unwrap_or(0),
+                ))
+            }
+            IsolationPolicy::TestBased { negatives: 0, .. } => Some(start),
+            IsolationPolicy::TestBased { negatives, .. } => {
+                let mut results: Vec<Time> = self
+                    .tests
+                    .iter()
+                    .filter(|t| start <= t.date && !t.positive && t.result_date <= date)
+                    .map(|t| t.result_date)
+                    .collect();
+                results.sort_unstable();
+                results.get(negatives - 1).copied()
+            }
+        }
+    }
+
+    /// Applies this person's isolation policy for the day, should be called once every time step
+    pub fn update_isolation(&mut self, date: Time) {
+        self.update_symptoms(date);
+        if let IsolationPolicy::TestBased {
+            interval, delay, ..
+        } = self.isolation_policy
+        {
+            if let Some(start) = self.isolation_start(date) {
+                let pending = self
+                    .tests
+                    .iter()
+                    .any(|t| start <= t.date && date < t.result_date);
+                let r = self.resolution;
+                let due = date > start && (date - start).is_multiple_of(r.days(max(interval, 1)));
+                if self.is_isolating(date) && due && !pending {
+                    self.test(date, delay);
+                }
             }
         }
-        false
     }
 
     /// Returns a reference to the current infection status
@@ -161,11 +524,10 @@ impl Person {
     /// Returns the number of days that this person was carrying the virus unaware
     pub fn days_unaware(self: &Self, date: Time) -> u64 {
         if let Some(infection) = &self.infection {
-            if min(infection.recovery_date, date) > infection.contagious_date {
-                min(infection.recovery_date, date) - infection.contagious_date
-            } else {
-                0
-            }
+            let aware = self.detection(date).map_or(date, |d| d.day);
+            let end = min(infection.recovery_date, min(date, aware));
+            self.resolution
+                .day(end.saturating_sub(infection.contagious_date))
         } else {
             0
         }
@@ -198,8 +560,90 @@ impl Person {
     */
 }
 
+impl Households {
+    /// Creates households from the household index of each person
+    pub fn new(of: Vec<usize>) -> Households {
+        Households {
+            of,
+            links: Vec::new(),
+        }
+    }
+
+    /// Puts each of the n people in their own household
+    pub fn individual(n: usize) -> Households {
+        Households::new((0..n).collect())
+    }
+
+    /// Links two households, e.g. the two homes of a child in shared custody
+    pub fn link(&mut self, a: usize, b: usize) {
+        self.links.push((a, b));
+    }
+
+    /// Moves a person to another household
+    pub fn move_person(&mut self, person: usize, household: usize) {
+        self.of[person] = household;
+    }
+
+    /// Moves everyone the cycle says should move on the given date
+    fn apply_moves(&mut self, cycle: &CyclicPattern, date: Time) {
+        let actions = cycle.actions.get(&((date + cycle.offset) % cycle.period));
+        for action in actions.into_iter().flatten() {
+            if let DailyAction::Move(person, household) = action {
+                self.move_person(*person, *household);
+            }
+        }
+    }
+
+    /// Household the person currently lives in
+    pub fn household(&self, person: usize) -> usize {
+        self.of[person]
+    }
+
+    /// Which people are isolating on the given date, once isolation is extended to `scope`
+    pub fn isolating(&self, people: &[Person], scope: IsolationScope, date: Time) -> Vec<bool> {
+        let individual: Vec<bool> = people.iter().map(|p| p.is_isolating(date)).collect();
+        if scope == IsolationScope::Individual {
+            return individual;
+        }
+
+        let mut isolating: HashSet<usize> = individual
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| **i)
+            .map(|(p, _)| self.of[p])
+            .collect();
+        if scope == IsolationScope::LinkedHouseholds {
+            for (a, b) in &self.links {
+                if isolating.contains(a) || isolating.contains(b) {
+                    isolating.insert(*a);
+                    isolating.insert(*b);
+                }
+            }
+        }
+
+        (0..people.len())
+            .map(|p| isolating.contains(&self.of[p]))
+            .collect()
+    }
+
+    /// Can these two people meet, given who is isolating? Members of an isolating household keep
+    /// seeing each other, unless isolation is individual
+    pub fn can_interact(
+        &self,
+        isolating: &[bool],
+        scope: IsolationScope,
+        a: usize,
+        b: usize,
+    ) -> bool {
+        if scope != IsolationScope::Individual && self.of[a] == self.of[b] {
+            return true;
+        }
+        !isolating[a] && !isolating[b]
+    }
+}
+
 /// Phase type
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Phase {
     /// B sees A
     A,
@@ -211,10 +655,22 @@ pub enum Phase {
     C,
 }
 
-/// Returns custom phase function
-pub fn gen_phase_fn(a: u64, ac: u64, c: u64, ca: u64, offset: u64) -> Box<dyn Fn(Time) -> Phase> {
-    let cycle_len = a + ac + c + ca;
-    Box::new(move |day| {
+/// Returns custom phase function, failing for a cycle without any days or too long to count
+pub fn gen_phase_fn(
+    a: u64,
+    ac: u64,
+    c: u64,
+    ca: u64,
+    offset: u64,
+) -> Result<Box<dyn Fn(Time) -> Phase + Send + Sync>, Error> {
+    let cycle_len = [ac, c, ca]
+        .iter()
+        .try_fold(a, |len, days| len.checked_add(*days))
+        .filter(|len| *len > 0)
+        .ok_or_else(|| {
+            Error::InvalidParameter(format!("phase cycle of {}/{}/{}/{}", a, ac, c, ca))
+        })?;
+    Ok(Box::new(move |day| {
         let cycle_day = (day + offset) % cycle_len;
         if cycle_day < a {
             Phase::A
@@ -225,10 +681,31 @@ pub fn gen_phase_fn(a: u64, ac: u64, c: u64, ca: u64, offset: u64) -> Box<dyn Fn
         } else {
             Phase::Isolate
         }
-    })
+    }))
+}
+
+/// Adjusts the scheduled phase to isolation: a handover into or out of an isolating household is
+/// skipped, and B stays where they currently are
+pub fn react_phase(
+    scheduled: Phase,
+    current: Phase,
+    a_isolating: bool,
+    c_isolating: bool,
+) -> Phase {
+    let blocked = |phase| match phase {
+        Phase::A => a_isolating,
+        Phase::C => c_isolating,
+        Phase::Isolate => false,
+    };
+    if scheduled != current && (blocked(current) || blocked(scheduled)) {
+        current
+    } else {
+        scheduled
+    }
 }
 
 /// An example phase function
+#[allow(clippy::manual_range_contains)]
 pub fn phase(day: u64) -> Phase {
     let cycle_day = day % (6 * 7);
     if cycle_day <= 15 {
@@ -245,6 +722,147 @@ pub fn phase(day: u64) -> Phase {
 pub struct PatternDesc {
     n_people: usize,
     cycles: Vec<CyclicPattern>,
+    households: Households,
+    scope: IsolationScope,
+    resolution: Resolution,
+    adherence: Vec<Adherence>,
+    symptoms: Vec<SymptomModel>,
+    ages: Vec<AgeGroup>,
+    disease: Disease,
+    isolation_policy: IsolationPolicy,
+}
+
+impl PatternDesc {
+    /// Creates a pattern of the given cycles for `n_people`, each in their own household, counted
+    /// in whole days. Everyone is an adult following the rules, with the default disease, and
+    /// isolates indefinitely
+    pub fn new(n_people: usize, cycles: Vec<CyclicPattern>) -> PatternDesc {
+        PatternDesc {
+            n_people,
+            cycles,
+            households: Households::individual(n_people),
+            scope: IsolationScope::default(),
+            resolution: Resolution::default(),
+            adherence: Vec::new(),
+            symptoms: Vec::new(),
+            ages: Vec::new(),
+            disease: Disease::default(),
+            isolation_policy: IsolationPolicy::default(),
+        }
+    }
+
+    /// Puts people in the given households, isolation extending to `scope` around them
+    pub fn with_households(mut self, households: Households, scope: IsolationScope) -> PatternDesc {
+        self.households = households;
+        self.scope = scope;
+        self
+    }
+
+    /// Counts the pattern's periods, offsets and start days in steps of the given resolution
+    pub fn with_resolution(mut self, resolution: Resolution) -> PatternDesc {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Checks the pattern has cycles, that none of them is empty, that all of its actions refer to
+    /// people in the pattern and that its test kinds, disease parameters and symptom models are in
+    /// range
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.cycles.is_empty() {
+            return Err(Error::EmptyPattern);
+        }
+        if self.households.of.len() != self.n_people {
+            return Err(Error::InvalidParameter(format!(
+                "households for {} people in a pattern of {}",
+                self.households.of.len(),
+                self.n_people
+            )));
+        }
+
+        for cycle in &self.cycles {
+            if cycle.period == 0 {
+                return Err(Error::InvalidParameter("cycle period of 0".to_string()));
+            }
+            for action in cycle.actions.values().flatten() {
+                let people = match action {
+                    DailyAction::Test(p) | DailyAction::Move(p, _) => vec![*p],
+                    DailyAction::Interact(a, b) => vec![*a, *b],
+                    DailyAction::PoolTest(pool, kind) => {
+                        kind.validate()?;
+                        pool.clone()
+                    }
+                };
+                for p in people {
+                    self.check_person(p)?;
+                }
+            }
+        }
+        for age in &[AgeGroup::Child, AgeGroup::Adult, AgeGroup::Senior] {
+            self.disease.params(*age).validate()?;
+        }
+        for adherence in &self.adherence {
+            adherence.validate()?;
+        }
+        for symptoms in &self.symptoms {
+            symptoms.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Households everyone lives in on the given date, after the last moves of each cycle up to
+    /// that date
+    fn households_at(&self, date: Time) -> Households {
+        let mut households = self.households.clone();
+        for cycle in &self.cycles {
+            for step in date + 1..=date + cycle.period {
+                households.apply_moves(cycle, step);
+            }
+        }
+        households
+    }
+
+    /// Checks the person is part of the pattern
+    fn check_person(&self, person: usize) -> Result<(), Error> {
+        if person < self.n_people {
+            Ok(())
+        } else {
+            Err(Error::UnknownPerson {
+                person,
+                n_people: self.n_people,
+            })
+        }
+    }
+
+    /// Gives each person the adherence at their index, anyone past the end adheres perfectly
+    pub fn with_adherence(mut self, adherence: Vec<Adherence>) -> PatternDesc {
+        self.adherence = adherence;
+        self
+    }
+
+    /// Gives each person the symptom model at their index, anyone past the end acts on their
+    /// symptoms the day they start and never gets colds
+    pub fn with_symptoms(mut self, symptoms: Vec<SymptomModel>) -> PatternDesc {
+        self.symptoms = symptoms;
+        self
+    }
+
+    /// Gives each person the age group at their index, anyone past the end is an adult
+    pub fn with_ages(mut self, ages: Vec<AgeGroup>) -> PatternDesc {
+        self.ages = ages;
+        self
+    }
+
+    /// Sets the disease parameters of every age group
+    pub fn with_disease(mut self, disease: Disease) -> PatternDesc {
+        self.disease = disease;
+        self
+    }
+
+    /// Sets the rules deciding when people stop isolating
+    pub fn with_isolation_policy(mut self, isolation_policy: IsolationPolicy) -> PatternDesc {
+        self.isolation_policy = isolation_policy;
+        self
+    }
 }
 
 /// A structure to describe actions that happen in a single date
@@ -255,9 +873,16 @@ pub enum DailyAction {
 
     /// The people with the given indices get interact
     Interact(usize, usize),
+
+    /// The people with the given indices get tested together in a single pool
+    PoolTest(Vec<usize>, TestKind),
+
+    /// The person with the given index moves to the given household, until they move again
+    Move(usize, usize),
 }
 
-/// Describes a singular periodic pattern
+/// Describes a singular periodic pattern, periods and offsets are in steps of the pattern's
+/// resolution
 #[derive(Debug)]
 pub struct CyclicPattern {
     period: u64,
@@ -265,52 +890,170 @@ pub struct CyclicPattern {
     actions: HashMap<Time, Vec<DailyAction>>,
 }
 
+impl CyclicPattern {
+    /// Creates a cycle repeating every `period` steps, doing the actions listed under each step of
+    /// the cycle. The cycle is shifted by `offset` steps
+    pub fn new(
+        period: u64,
+        offset: u64,
+        actions: HashMap<Time, Vec<DailyAction>>,
+    ) -> CyclicPattern {
+        CyclicPattern {
+            period,
+            offset,
+            actions,
+        }
+    }
+
+    /// Next day after `date` on which this cycle has something to do. Interactions only matter
+    /// while someone is contagious, tests always do
+    fn next_action(&self, date: Time, interactions: bool) -> Option<Time> {
+        (date + 1..=date + self.period).find(|day| {
+            self.actions
+                .get(&((day + self.offset) % self.period))
+                .is_some_and(|actions| {
+                    actions.iter().any(|a| match a {
+                        DailyAction::Test(_)
+                        | DailyAction::PoolTest(_, _)
+                        | DailyAction::Move(_, _) => true,
+                        DailyAction::Interact(_, _) => interactions,
+                    })
+                })
+        })
+    }
+}
+
 /// Runs through a cycle, testing each (person day) sick combination n times
-pub fn run_pattern(pattern: &PatternDesc, n: u64) {
+pub fn run_pattern(pattern: &PatternDesc, n: u64) -> Result<(), Error> {
+    pattern.validate()?;
     let pattern_period = pattern
         .cycles
         .iter()
-        .max_by_key(|c| c.period)
-        .unwrap()
-        .period;
+        .map(|c| c.period)
+        .max()
+        .ok_or(Error::EmptyPattern)?;
     for day in 0..pattern_period {
         for p in 0..pattern.n_people {
             for _ in 0..n {
-                run_single(pattern, p, day);
+                println!("running {} {} of {:?}", p, day, pattern);
+                run_single(pattern, p, day, false, None)?;
             }
         }
     }
+    Ok(())
+}
+
+/// Runs a single outbreak of the pattern, started by `person` on `start_day`, recording every
+/// event along the way
+pub fn trace_single(pattern: &PatternDesc, person: usize, start_day: Time) -> Result<Trace, Error> {
+    pattern.validate()?;
+    let mut trace = Trace::new();
+    run_single(pattern, person, start_day, false, Some(&mut trace))?;
+    Ok(trace)
 }
 
-fn run_single(pattern: &PatternDesc, person: usize, start_day: Time) {
-    println!("running {} {} of {:?}", person, start_day, pattern);
+/// Summary of a single outbreak of a pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatternOutcome {
+    /// Number of people infected, including the first one
+    pub infected: usize,
+
+    /// Number of tests used, pools and reflex tests included
+    pub tests_used: usize,
+
+    /// Time from the first exposure to the first person isolating, if anyone ever did
+    pub detection: Option<Time>,
+
+    /// What gave the outbreak away, if anything did
+    pub detection_route: Option<DetectionRoute>,
+}
+
+/// Runs a single outbreak of the pattern, started by `person` on `start_day`
+pub fn run_outbreak(
+    pattern: &PatternDesc,
+    person: usize,
+    start_day: Time,
+) -> Result<PatternOutcome, Error> {
+    pattern.validate()?;
+    let run = run_single(pattern, person, start_day, false, None)?;
+    let end = pattern.resolution.days(HORIZON);
+    let detection = first_detection(&run.people, end);
+    Ok(PatternOutcome {
+        infected: run.people.iter().filter(|p| p.was_sick(end)).count(),
+        tests_used: run.tests_used,
+        detection: detection.map(|d| d.day - start_day),
+        detection_route: detection.map(|d| d.route),
+    })
+}
+
+/// Everyone at the end of a single run, and the number of tests it took
+struct Run {
+    people: Vec<Person>,
+    tests_used: usize,
+}
+
+/// Runs a single outbreak started by `person` on `start_day`. In the first generation only, nobody
+/// but that person passes the virus on
+fn run_single(
+    pattern: &PatternDesc,
+    person: usize,
+    start_day: Time,
+    first_generation: bool,
+    trace: Option<&mut Trace>,
+) -> Result<Run, Error> {
+    let agenda = Agenda::new();
+    run_on_agenda(pattern, person, start_day, first_generation, trace, agenda)
+}
+
+/// Same as `run_single`, waking up on the days the given agenda says rather than only on those
+/// where something can happen
+fn run_on_agenda(
+    pattern: &PatternDesc,
+    person: usize,
+    start_day: Time,
+    first_generation: bool,
+    mut trace: Option<&mut Trace>,
+    mut agenda: Agenda,
+) -> Result<Run, Error> {
+    pattern.check_person(person)?;
 
     // People init
     let mut people = Vec::new();
-    for _ in 0..pattern.n_people {
-        people.push(Person::new());
+    for i in 0..pattern.n_people {
+        let adherence = pattern.adherence.get(i).copied().unwrap_or_default();
+        let symptoms = pattern.symptoms.get(i).copied().unwrap_or_default();
+        let age = pattern.ages.get(i).copied().unwrap_or_default();
+        people.push(
+            Person::new()
+                .with_id(i)
+                .at_resolution(pattern.resolution)
+                .with_isolation_policy(pattern.isolation_policy)
+                .with_adherence(adherence)?
+                .with_symptoms(symptoms)?
+                .with_age(age, &pattern.disease)?,
+        );
     }
 
     // Expose relevant person
-    people[person].expose(start_day);
-
-    // Run each day
-    for day in start_day..400 {
-        // Check if we're done
-        let mut anyone_isolating = false;
-        let mut everyone_recovered = true;
-        for p in &people {
-            if p.is_isolating(day) {
-                anyone_isolating = true;
-            }
-            if p.has_recovered(day) {
-                everyone_recovered = false;
-            }
+    people[person].try_expose(start_day)?;
+
+    // Only run the days where something can happen
+    let mut tests_used = 0;
+    let mut households = pattern.households_at(start_day);
+    agenda.schedule(start_day);
+    while let Some(day) = agenda.next() {
+        // Check if we're done, isolating people simply sit out their interactions
+        if day >= pattern.resolution.days(HORIZON) || people.iter().all(|p| p.has_recovered(day)) {
+            break;
         }
 
-        if anyone_isolating || everyone_recovered {
-            break;
+        for p in &mut people {
+            p.update_isolation(day);
         }
+        for cycle in &pattern.cycles {
+            households.apply_moves(cycle, day);
+        }
+        let isolating = households.isolating(&people, pattern.scope, day);
 
         // check each cycle
         for cycle in &pattern.cycles {
@@ -321,20 +1064,81 @@ fn run_single(pattern: &PatternDesc, person: usize, start_day: Time) {
             if let Some(actions) = cycle.actions.get(&cycle_day) {
                 for a in actions {
                     match a {
-                        DailyAction::Test(p_id) => people[*p_id].test(day, 1),
+                        // moves were made before checking who isolates
+                        DailyAction::Move(_, _) => {}
+                        DailyAction::Test(p_id) => {
+                            if people[*p_id].takes_scheduled_test() {
+                                people[*p_id].test(day, 1);
+                                tests_used += 1;
+                            }
+                        }
+                        DailyAction::PoolTest(pool, kind) => {
+                            let pool: Vec<usize> = pool
+                                .iter()
+                                .copied()
+                                .filter(|p| people[*p].takes_scheduled_test())
+                                .collect();
+                            if !pool.is_empty() {
+                                tests_used += pool_test(&mut people, &pool, day, *kind)?;
+                            }
+                        }
                         DailyAction::Interact(p_a, p_b) => {
-                            if people[*p_a].is_contagious(day) {
-                                people[*p_b].expose(day);
+                            // only draw who breaks isolation for meetings that can pass the virus
+                            // on, or that get traced, so that idle days stay idle
+                            let harmless = !people[*p_a].is_contagious(day)
+                                && !people[*p_b].is_contagious(day);
+                            if (harmless && trace.is_none())
+                                || !households.can_meet(
+                                    &people,
+                                    &isolating,
+                                    pattern.scope,
+                                    *p_a,
+                                    *p_b,
+                                )
+                            {
+                                continue;
                             }
-                            if people[*p_b].is_contagious(day) {
-                                people[*p_a].expose(day);
+                            if let Some(trace) = trace.as_mut() {
+                                trace.record(Event::Interaction {
+                                    time: day,
+                                    a: *p_a,
+                                    b: *p_b,
+                                });
+                            }
+                            let spreads = |p: usize| !first_generation || p == person;
+                            let (a, b) = (&people[*p_a], &people[*p_b]);
+                            if spreads(*p_a) && a.is_contagious(day) && transmits(a, b, 1.) {
+                                let source = a.infection;
+                                people[*p_b].try_expose_from(day, source, Some(*p_a))?;
+                            }
+                            let (a, b) = (&people[*p_a], &people[*p_b]);
+                            if spreads(*p_b) && b.is_contagious(day) && transmits(b, a, 1.) {
+                                let source = b.infection;
+                                people[*p_a].try_expose_from(day, source, Some(*p_b))?;
                             }
                         }
                     }
                 }
             }
         }
+
+        if let Some(trace) = trace.as_mut() {
+            for (i, p) in people.iter().enumerate() {
+                trace.observe(day, i, p);
+            }
+        }
+
+        // Traces keep every interaction, even those that can't infect anyone
+        let interactions = trace.is_some() || people.iter().any(|p| p.is_contagious(day));
+        agenda.schedule_people(day, &people);
+        for cycle in &pattern.cycles {
+            if let Some(next) = cycle.next_action(day, interactions) {
+                agenda.schedule(next);
+            }
+        }
     }
+
+    Ok(Run { people, tests_used })
 }
 
 #[cfg(test)]
@@ -344,6 +1148,7 @@ mod test {
 
     #[test]
     fn single_infected_tested_isolating() {
+        reseed(1);
         let mut me = Person::new_str("Olivia".to_string());
         //assert_eq!(me.name, "Olivia".to_string());
 
@@ -364,6 +1169,7 @@ mod test {
 
     #[test]
     fn interaction_abc_future() {
+        reseed(1);
         let mut a = Person::new_str("Olivia A".to_string());
         let mut b = Person::new_str("Olivia B".to_string());
         let mut c = Person::new_str("Olivia C".to_string());
@@ -390,6 +1196,7 @@ mod test {
 
     #[test]
     fn interaction() {
+        reseed(1);
         let mut healthy_me = Person::new_str("Olivia Healthy".to_string());
         let mut sick_me = Person::new_str("Olivia Sick".to_string());
         assert!(!healthy_me.was_sick(0));
@@ -443,6 +1250,7 @@ mod test {
 
     #[test]
     fn delay_testing() {
+        reseed(1);
         for _ in 0..5_000 {
             let mut me = Person::new_str("Olivia".to_string());
             me.expose(100);
@@ -482,12 +1290,97 @@ mod test {
 
     #[test]
     fn symptomatic_distribution_quantiles() {
+        let percentiles = vec![
+            (02.5, 1.92),
+            (05., 2.24),
+            (10., 2.69),
+            (25., 3.64),
+            (50., 5.10),
+            (75., 7.15),
+            (90., 9.69),
+            (95., 11.60),
+            (97.5, 13.60),
+        ];
+        assert_incubation_quantiles(&Disease::default(), percentiles);
+    }
+
+    #[test]
+    fn incubation_distribution_quantiles() {
+        // https://www.acpjournals.org/doi/10.7326/M20-0504
+        let gamma = Period::Gamma {
+            shape: 5.807,
+            scale: 0.948,
+        };
+        let percentiles = vec![
+            (02.5, 1.98),
+            (05., 2.36),
+            (10., 2.85),
+            (25., 3.84),
+            (50., 5.19),
+            (75., 6.83),
+            (90., 8.56),
+            (95., 9.72),
+            (97.5, 10.80),
+        ];
+        assert_incubation_quantiles(&incubation(gamma), percentiles);
+
+        // https://www.eurosurveillance.org/content/10.2807/1560-7917.ES.2020.25.5.2000062
+        let (shape, scale) = (3.0, 7.2);
+        let weibull = Period::Weibull { shape, scale };
+        let percentiles = [2.5, 5., 10., 25., 50., 75., 90., 95., 97.5]
+            .iter()
+            .map(|p: &f64| (*p, scale * (-(1. - p / 100.).ln()).powf(1. / shape)))
+            .collect();
+        assert_incubation_quantiles(&incubation(weibull), percentiles);
+
+        // whole days, percentiles picked away from the jumps of the distribution
+        let pmf = "# days probability\n2 0.1\n3 0.15\n\n4 0.25\n5 0.25\n6 0.15\n8 0.1\n";
+        let empirical = Period::from_pmf(pmf.as_bytes()).unwrap();
+        let percentiles = vec![
+            (5., 1.6),
+            (15., 2.6),
+            (40., 3.6),
+            (60., 4.6),
+            (85., 5.6),
+            (95., 7.6),
+        ];
+        assert_incubation_quantiles(&incubation(empirical.clone()), percentiles);
+
+        // symptoms wait for the latent period to be over, shorter incubations all end up at its
+        // end: half of them on day 4 here
+        let latent = Disease::uniform(DiseaseParams {
+            incubation: empirical,
+            latent: Period::Fixed(4.),
+            ..DiseaseParams::default()
+        });
+        let percentiles = vec![(5., 3.6), (45., 3.6), (55., 4.6), (85., 5.6), (95., 7.6)];
+        assert_incubation_quantiles(&latent, percentiles);
+
+        assert!(Period::from_pmf("1 0.5 2".as_bytes()).is_err());
+        assert!(Period::from_pmf("1 -0.5".as_bytes()).is_err());
+        assert!(Period::from_pmf("# nothing".as_bytes()).is_err());
+    }
+
+    /// The default disease with another incubation period
+    fn incubation(incubation: Period) -> Disease {
+        Disease::uniform(DiseaseParams {
+            incubation,
+            ..DiseaseParams::default()
+        })
+    }
+
+    /// Checks the percentiles of the incubation period of symptomatic cases, each falling on the
+    /// right whole day
+    #[allow(clippy::unnecessary_lazy_evaluations)]
+    fn assert_incubation_quantiles(disease: &Disease, percentiles: Vec<(f64, f64)>) {
         let mut sympt_dist = HashMap::new();
         let mut n_tot = 0;
 
         // Get 10k samples where symptoms are shown
         while n_tot < 10_000 {
-            let mut me = Person::new_str("Olivia".to_string());
+            let mut me = Person::new_str("Olivia".to_string())
+                .with_age(AgeGroup::Adult, disease)
+                .unwrap();
             me.expose(100);
 
             let infection = me.get_infection().unwrap();
@@ -515,18 +1408,6 @@ mod test {
         }
         assert!(prev == n_tot, "Expected prev {} == n_tot {}", prev, n_tot);
 
-        let percentiles: Vec<(f64, f64)> = vec![
-            (02.5, 1.92),
-            (05., 2.24),
-            (10., 2.69),
-            (25., 3.64),
-            (50., 5.10),
-            (75., 7.15),
-            (90., 9.69),
-            (95., 11.60),
-            (97.5, 13.60),
-        ];
-
         // because we're dealing with integer days, make sure that the percentiles fall on the
         // right day
         for (p, expected) in percentiles {
@@ -551,7 +1432,7 @@ mod test {
 
     #[test]
     fn gen_phase_fn_test() {
-        let phase_fn = gen_phase_fn(16, 5, 16, 5, 0);
+        let phase_fn = gen_phase_fn(16, 5, 16, 5, 0).unwrap();
         for day in 0..100 {
             assert_eq!(
                 phase_fn(day),
@@ -566,7 +1447,7 @@ mod test {
 
     #[test]
     fn gen_phase_fn_test_hardcoded() {
-        let alt_fn = gen_phase_fn(1, 0, 1, 0, 0);
+        let alt_fn = gen_phase_fn(1, 0, 1, 0, 0).unwrap();
         for day in 0..100 {
             if day % 2 == 0 {
                 assert_eq!(
@@ -589,9 +1470,9 @@ mod test {
             }
         }
 
-        let none_fn_1 = gen_phase_fn(0, 12, 0, 0, 0);
-        let none_fn_2 = gen_phase_fn(0, 0, 0, 1, 0);
-        let none_fn_3 = gen_phase_fn(0, 2, 0, 6, 0);
+        let none_fn_1 = gen_phase_fn(0, 12, 0, 0, 0).unwrap();
+        let none_fn_2 = gen_phase_fn(0, 0, 0, 1, 0).unwrap();
+        let none_fn_3 = gen_phase_fn(0, 2, 0, 6, 0).unwrap();
         for day in 0..100 {
             assert_eq!(
                 none_fn_1(day),
@@ -616,8 +1497,10 @@ mod test {
             );
         }
 
-        let always_a = gen_phase_fn(5, 0, 0, 0, 0);
-        let always_c = gen_phase_fn(0, 0, 9, 0, 0);
+        assert!(gen_phase_fn(0, 0, 0, 0, 3).is_err());
+        assert!(gen_phase_fn(Time::MAX, 0, 1, 0, 0).is_err());
+        let always_a = gen_phase_fn(5, 0, 0, 0, 0).unwrap();
+        let always_c = gen_phase_fn(0, 0, 9, 0, 0).unwrap();
         for day in 0..100 {
             assert_eq!(always_a(day), Phase::A);
             assert_eq!(always_c(day), Phase::C);
@@ -626,6 +1509,502 @@ mod test {
 
     #[test]
     fn pattern_test() {
-        assert!(true);
+        let mut actions = HashMap::new();
+        actions.insert(0, vec![DailyAction::Interact(0, 1)]);
+        let pattern = PatternDesc {
+            n_people: 2,
+            cycles: vec![CyclicPattern {
+                period: 1,
+                offset: 0,
+                actions,
+            }],
+            households: Households::individual(2),
+            scope: IsolationScope::Individual,
+            resolution: Resolution::DAY,
+            adherence: Vec::new(),
+            symptoms: Vec::new(),
+            ages: Vec::new(),
+            disease: Disease::default(),
+            isolation_policy: IsolationPolicy::default(),
+        };
+
+        for _ in 0..1_000 {
+            let people = run_single(&pattern, 0, 10, false, None).unwrap().people;
+            let source = people[0].get_infection().unwrap();
+            if let Some(infection) = people[1].get_infection() {
+                // only infected once contagious, and while not isolating
+                assert!(source.contagious_date <= infection.date);
+                assert!(!people[0].is_isolating(infection.date));
+            }
+        }
+    }
+
+    #[test]
+    fn pattern_symptoms() {
+        let pattern = |symptoms: SymptomModel| {
+            let mut actions = HashMap::new();
+            actions.insert(0, vec![DailyAction::Interact(0, 1)]);
+            PatternDesc::new(2, vec![CyclicPattern::new(1, 0, actions)])
+                .with_symptoms(vec![symptoms; 2])
+        };
+        let ignore = SymptomModel {
+            ignore: 1.,
+            ..SymptomModel::default()
+        };
+        let colds = SymptomModel {
+            background_rate: 0.2,
+            ..SymptomModel::default()
+        };
+
+        // without tests, ignoring symptoms means nobody ever finds out
+        let mut noticed = 0;
+        let mut with_colds = 0;
+        for _ in 0..100 {
+            let run = run_single(&pattern(ignore), 0, 10, false, None).unwrap();
+            assert!(run.people.iter().all(|p| p.detection(HORIZON).is_none()));
+
+            let run = run_single(&pattern(SymptomModel::default()), 0, 10, false, None).unwrap();
+            noticed += run.people[0].detection(HORIZON).is_some() as usize;
+
+            let run = run_single(&pattern(colds), 0, 10, false, None).unwrap();
+            with_colds += run.people.iter().any(|p| !p.colds.is_empty()) as usize;
+        }
+        assert!(noticed > 0);
+        assert!(with_colds > 0);
+
+        let invalid = SymptomModel {
+            ignore: f64::NAN,
+            ..SymptomModel::default()
+        };
+        assert!(matches!(
+            run_outbreak(&pattern(invalid), 0, 10),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn pattern_isolation_policy() {
+        let daily_test = || {
+            let mut actions = HashMap::new();
+            actions.insert(0, vec![DailyAction::Test(0)]);
+            PatternDesc::new(1, vec![CyclicPattern::new(1, 0, actions)])
+        };
+        let pattern = daily_test();
+        let released = daily_test().with_isolation_policy(IsolationPolicy::Fixed(7));
+
+        // tested every day, so always found out, but only back in the schedule when released
+        for _ in 0..100 {
+            let me = &run_single(&pattern, 0, 10, false, None).unwrap().people[0];
+            assert!(me.detection(HORIZON).is_some());
+            assert!(me.is_isolating(HORIZON - 1));
+
+            let me = &run_single(&released, 0, 10, false, None).unwrap().people[0];
+            let start = me.isolation_start(HORIZON).unwrap();
+            assert!(me.is_isolating(start));
+            assert!(!me.is_isolating(HORIZON - 1));
+        }
+    }
+
+    #[test]
+    fn agenda_matches_daily_loop() {
+        let pattern = |adherence: Vec<Adherence>| {
+            let mut actions = HashMap::new();
+            actions.insert(
+                0,
+                vec![DailyAction::Interact(0, 1), DailyAction::Interact(1, 2)],
+            );
+            actions.insert(1, vec![DailyAction::Test(2)]);
+            actions.insert(
+                3,
+                vec![
+                    DailyAction::Interact(2, 3),
+                    DailyAction::PoolTest(vec![0, 1, 3], TestKind::PCR),
+                ],
+            );
+            PatternDesc {
+                n_people: 4,
+                cycles: vec![CyclicPattern {
+                    period: 7,
+                    offset: 0,
+                    actions,
+                }],
+                households: Households::new(vec![0, 0, 1, 1]),
+                scope: IsolationScope::Household,
+                resolution: Resolution::DAY,
+                adherence,
+                symptoms: Vec::new(),
+                ages: vec![AgeGroup::Child, AgeGroup::Adult, AgeGroup::Senior],
+                disease: Disease::age_structured(),
+                isolation_policy: IsolationPolicy::default(),
+            }
+        };
+        let sloppy = Adherence {
+            skip_test: 0.3,
+            break_isolation: 0.5,
+            unreported: 0.2,
+        };
+
+        // same seed, same draws, as long as the skipped days are really idle
+        for pattern in &[pattern(Vec::new()), pattern(vec![sloppy; 4])] {
+            for seed in 0..200 {
+                let run = |agenda| {
+                    reseed(seed);
+                    run_on_agenda(pattern, 0, 3, false, None, agenda).unwrap()
+                };
+                let (skipping, daily) = (run(Agenda::new()), run(Agenda::daily()));
+                assert_eq!(skipping.tests_used, daily.tests_used);
+                assert_eq!(
+                    format!("{:?}", skipping.people),
+                    format!("{:?}", daily.people)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn isolation_fixed() {
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_isolation_policy(IsolationPolicy::Fixed(7));
+            me.expose(100);
+
+            let t = me.get_infection().unwrap().testable_date;
+            me.test(t, 0);
+
+            let start = me.isolation_start(t).unwrap();
+            assert!(start <= t);
+            assert!(me.is_isolating(start));
+            assert!(me.is_isolating(start + 6));
+            assert!(!me.is_isolating(start + 7));
+        }
+    }
+
+    #[test]
+    fn isolation_again() {
+        let asymptomatic = Disease::uniform(DiseaseParams {
+            symptomatic_fraction: 0.,
+            ..DiseaseParams::default()
+        });
+        for _ in 0..1_000 {
+            let mut me = Person::new()
+                .with_isolation_policy(IsolationPolicy::Fixed(3))
+                .with_age(AgeGroup::Adult, &asymptomatic)
+                .unwrap();
+            me.expose(100);
+            let infection = me.get_infection().unwrap();
+            let (t, end) = (infection.testable_date, infection.testable_end);
+            assert!(end > t + 4);
+
+            // a positive result while isolating doesn't extend the isolation
+            me.test(t, 0);
+            me.test(t + 1, 0);
+            assert_eq!(me.isolation_start(t + 2), Some(t));
+            assert!(!me.is_isolating(t + 3));
+
+            // one after release starts a new isolation
+            me.test(end, 0);
+            assert!(!me.is_isolating(end - 1));
+            assert_eq!(me.isolation_start(end), Some(end));
+            assert!(me.is_isolating(end + 2));
+            assert!(!me.is_isolating(end + 3));
+            assert_eq!(me.detection(end).unwrap().day, t);
+        }
+    }
+
+    #[test]
+    fn isolation_symptom_based() {
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_isolation_policy(IsolationPolicy::SymptomBased(5));
+            me.expose(100);
+
+            let infection = me.get_infection().unwrap();
+            if let Some(s) = infection.symptomatic_date {
+                // still isolating as long as the symptoms last
+                assert!(me.is_isolating(s + 5));
+                assert!(me.is_isolating(infection.recovery_date));
+                assert!(!me.is_isolating(infection.recovery_date + 1));
+            } else {
+                let t = infection.testable_date;
+                me.test(t, 0);
+                assert!(me.is_isolating(t + 4));
+                assert!(!me.is_isolating(t + 5));
+            }
+        }
+    }
+
+    #[test]
+    fn isolation_test_based() {
+        let policy = IsolationPolicy::TestBased {
+            negatives: 2,
+            interval: 1,
+            delay: 1,
+        };
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_isolation_policy(policy);
+            me.expose(100);
+
+            let infection = me.get_infection().unwrap();
+            me.test(infection.testable_date, 0);
+            for day in 100..200 {
+                me.update_isolation(day);
+            }
+
+            // only released once two negative tests come back, after recovery
+            let start = me.isolation_start(200).unwrap();
+            assert!(me.is_isolating(infection.recovery_date + 2));
+            assert!(!me.is_isolating(infection.recovery_date + 3));
+            assert!(!me.is_isolating(200));
+            assert!(start <= infection.testable_date);
+        }
+    }
+
+    #[test]
+    fn household_isolation_scopes() {
+        let mut people = vec![Person::new(), Person::new(), Person::new(), Person::new()];
+        people[0].expose(100);
+        let t = people[0].get_infection().unwrap().testable_date;
+        people[0].test(t, 0);
+
+        // 0 and 1 share a home, linked to 2's home, 3 is on their own
+        let mut households = Households::new(vec![0, 0, 1, 2]);
+        households.link(0, 1);
+
+        let individual = households.isolating(&people, IsolationScope::Individual, t);
+        assert_eq!(individual, vec![true, false, false, false]);
+        let household = households.isolating(&people, IsolationScope::Household, t);
+        assert_eq!(household, vec![true, true, false, false]);
+        let linked = households.isolating(&people, IsolationScope::LinkedHouseholds, t);
+        assert_eq!(linked, vec![true, true, true, false]);
+
+        // isolating households keep meeting among themselves only
+        assert!(households.can_interact(&household, IsolationScope::Household, 0, 1));
+        assert!(!households.can_interact(&household, IsolationScope::Household, 1, 2));
+        assert!(!households.can_interact(&individual, IsolationScope::Individual, 0, 1));
+        assert!(households.can_interact(&linked, IsolationScope::LinkedHouseholds, 2, 2));
+    }
+
+    #[test]
+    fn react_phase_skips_handovers() {
+        // no isolation, follow the schedule
+        assert_eq!(react_phase(Phase::C, Phase::A, false, false), Phase::C);
+
+        // either household isolating, stay put
+        assert_eq!(react_phase(Phase::C, Phase::A, true, false), Phase::A);
+        assert_eq!(react_phase(Phase::C, Phase::A, false, true), Phase::A);
+        assert_eq!(
+            react_phase(Phase::A, Phase::Isolate, true, false),
+            Phase::Isolate
+        );
+
+        // no handover, nothing to skip
+        assert_eq!(react_phase(Phase::A, Phase::A, true, true), Phase::A);
+    }
+
+    #[test]
+    fn sub_day_resolution() {
+        let r = Resolution::HOUR;
+        assert_eq!(r.days(2), 48);
+        assert_eq!(r.day(47), 1);
+        assert_eq!(Resolution::new(24), Ok(r));
+        assert!(Resolution::new(0).is_err());
+
+        for _ in 0..1_000 {
+            // exposed during an 8am handover
+            let mut me = Person::new().at_resolution(r);
+            me.expose(r.days(100) + 8);
+
+            let infection = me.get_infection().unwrap();
+            assert!(infection.contagious_date >= r.days(101) + 8);
+            if infection.symptomatic_date.is_some() {
+                continue;
+            }
+
+            // tested in the morning, results come back the next morning
+            let t = infection.testable_date;
+            me.test(t, 1);
+            assert!(!me.is_isolating(t + 23));
+            assert!(me.is_isolating(t + 24));
+            assert_eq!(
+                me.days_unaware(t + 24),
+                r.day(t + 24 - infection.contagious_date)
+            );
+        }
+    }
+
+    #[test]
+    fn sub_day_pattern() {
+        // a test at 8am and a handover at 6pm, every day
+        let r = Resolution::HOUR;
+        let mut actions = HashMap::new();
+        actions.insert(8, vec![DailyAction::Test(0)]);
+        actions.insert(18, vec![DailyAction::Interact(0, 1)]);
+        let pattern =
+            PatternDesc::new(2, vec![CyclicPattern::new(r.days(1), 0, actions)]).with_resolution(r);
+
+        for _ in 0..100 {
+            let trace = trace_single(&pattern, 0, r.days(10) + 12).unwrap();
+            let isolated = trace.events().iter().find_map(|e| match e {
+                Event::IsolationStart { time, person: 0 } => Some(*time),
+                _ => None,
+            });
+            for event in trace.events() {
+                match *event {
+                    Event::Test { time, .. } | Event::Result { time, .. } => {
+                        assert_eq!(time % 24, 8)
+                    }
+                    Event::Interaction { time, .. }
+                    | Event::Exposure {
+                        time, person: 1, ..
+                    } => {
+                        assert_eq!(time % 24, 18);
+                        assert!(isolated.is_none_or(|i| time < i));
+                    }
+                    _ => {}
+                }
+            }
+            assert!(trace
+                .events()
+                .iter()
+                .any(|e| matches!(e, Event::Test { .. })));
+
+            // the seed came from outside, the other one can only have caught it from them
+            for event in trace.events() {
+                if let Event::Exposure { person, source, .. } = *event {
+                    assert_eq!(source, if person == 0 { None } else { Some(0) });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_on_day_zero() {
+        let early = Disease::uniform(DiseaseParams {
+            incubation: Period::Fixed(0.),
+            ..DiseaseParams::default()
+        });
+        for _ in 0..1_000 {
+            for r in &[Resolution::DAY, Resolution::HOUR] {
+                let mut me = Person::new().at_resolution(*r);
+                me.try_expose(0).unwrap();
+                let infection = me.get_infection().unwrap();
+                assert!(infection.contagious_date >= r.days(1));
+
+                // symptoms on the day of exposure wait for the latent period to be over
+                let mut me = Person::new()
+                    .at_resolution(*r)
+                    .with_age(AgeGroup::Adult, &early)
+                    .unwrap();
+                me.try_expose(0).unwrap();
+                let infection = me.get_infection().unwrap();
+                assert_eq!(infection.contagious_date, r.days(1));
+                assert_eq!(infection.testable_date, r.days(1));
+                assert_eq!(infection.recovery_date, r.days(11));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_disease_parameters() {
+        let invalid = |params: DiseaseParams| {
+            let mut me = Person::new().with_age(AgeGroup::Adult, &Disease::uniform(params))?;
+            let result = me.try_expose(0);
+            assert!(me.get_infection().is_none());
+            result
+        };
+
+        let fraction = DiseaseParams {
+            symptomatic_fraction: 1.5,
+            ..DiseaseParams::default()
+        };
+        assert!(matches!(invalid(fraction), Err(Error::InvalidParameter(_))));
+
+        let gamma = DiseaseParams {
+            infectious: Period::Gamma {
+                shape: -1.,
+                scale: 2.,
+            },
+            ..DiseaseParams::default()
+        };
+        assert!(matches!(invalid(gamma), Err(Error::InvalidParameter(_))));
+
+        let negative = DiseaseParams {
+            latent: Period::Fixed(-1.),
+            ..DiseaseParams::default()
+        };
+        assert!(matches!(invalid(negative), Err(Error::InvalidParameter(_))));
+
+        let forever = DiseaseParams {
+            infectious: Period::Fixed(1e30),
+            ..DiseaseParams::default()
+        };
+        assert_eq!(invalid(forever), Err(Error::TimeOverflow));
+
+        let mut me = Person::new();
+        assert_eq!(me.try_expose(Time::MAX - 1), Err(Error::TimeOverflow));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let pattern = |n_people: usize, cycles: Vec<CyclicPattern>| PatternDesc {
+            n_people,
+            cycles,
+            households: Households::individual(n_people),
+            scope: IsolationScope::Individual,
+            resolution: Resolution::DAY,
+            adherence: Vec::new(),
+            symptoms: Vec::new(),
+            ages: Vec::new(),
+            disease: Disease::default(),
+            isolation_policy: IsolationPolicy::default(),
+        };
+        let cycle = |period: Time, action: DailyAction| {
+            let mut actions = HashMap::new();
+            actions.insert(0, vec![action]);
+            CyclicPattern {
+                period,
+                offset: 0,
+                actions,
+            }
+        };
+
+        let empty = pattern(2, Vec::new());
+        assert_eq!(empty.validate(), Err(Error::EmptyPattern));
+        assert_eq!(run_pattern(&empty, 1), Err(Error::EmptyPattern));
+
+        let stranger = pattern(2, vec![cycle(1, DailyAction::Interact(0, 2))]);
+        let unknown = Error::UnknownPerson {
+            person: 2,
+            n_people: 2,
+        };
+        assert_eq!(run_outbreak(&stranger, 0, 0), Err(unknown.clone()));
+
+        let valid = pattern(2, vec![cycle(1, DailyAction::Test(1))]);
+        assert!(valid.validate().is_ok());
+        assert_eq!(trace_single(&valid, 2, 0).err(), Some(unknown));
+        assert!(run_outbreak(&valid, 0, 0).is_ok());
+
+        let never = pattern(2, vec![cycle(0, DailyAction::Test(1))]);
+        assert!(matches!(
+            run_outbreak(&never, 0, 0),
+            Err(Error::InvalidParameter(_))
+        ));
+
+        // bad test kinds and disease parameters are caught before anyone gets exposed
+        let blunt = TestKind {
+            sensitivity: 1.5,
+            ..TestKind::PCR
+        };
+        let pool = pattern(2, vec![cycle(1, DailyAction::PoolTest(vec![0, 1], blunt))]);
+        assert!(matches!(
+            secondary_cases(&pool, 1),
+            Err(Error::InvalidParameter(_))
+        ));
+        let sick = valid.with_disease(Disease::uniform(DiseaseParams {
+            susceptibility: f64::NAN,
+            ..DiseaseParams::default()
+        }));
+        assert!(matches!(
+            run_pattern(&sick, 1),
+            Err(Error::InvalidParameter(_))
+        ));
     }
 }
@@ -4,72 +4,646 @@
 //! ???
 
 use rayon::prelude::*;
-use rustagious::{gen_phase_fn, Person, Phase};
-use std::collections::HashMap;
+use rustagious::{
+    first_detection, gen_phase_fn, optimize, rank_testing, react_phase, reseed, stream_seed,
+    Adherence, AgeGroup, Agenda, Checkpoints, Constraints, ContactNetwork, Detection,
+    DetectionRoute, Disease, Estimate, Event, Generations, Households, IsolationPolicy,
+    IsolationScope, Person, Phase, Schedule, ScheduledTest, SecondaryCases, Seir, SeirState,
+    SevereRisk, SymptomModel, TestKind, TestingSchedule, Trace,
+};
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::ops::AddAssign;
+use std::sync::Mutex;
 
 //type Res = (String, u64, String, u64, String, u64);
 type Res = (u64, u64);
 
+/// Who a trial was seeded with, 1 to 3 for A to C, and how it ended
+type Key = (u64, Res);
+
+/// Last day simulated by a trial
+const HORIZON: u64 = 300;
+
 fn main() {
-    println!("a, ac, c, ca, offset, a_test, b_test, n, tot_days_unaware, n_infected");
-    let n = 100_000;
-    //for (a, ac, c, ca) in gen_phases() {
-    #[allow(clippy::print_literal)]
-    gen_phases()
-        .par_iter()
-        .map(move |phase_desc| {
-            let (a, ac, c, ca, offset) = *phase_desc;
-            let cycle_len = a + ac + c + ca;
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("trace") => trace(&args[2..]),
+        Some("network") => network(&args[2..]),
+        Some("seir") => seir(&args[2..]),
+        Some("secondary") => secondary(&args[2..]),
+        Some("breakdown") => breakdown(&args[2..]),
+        Some("detection") => detection(&args[2..]),
+        Some("optimize") => optimize_schedules(&args[2..]),
+        Some("testing") => optimize_testing(&args[2..]),
+        Some("sweep") => sweep(&args[2..]),
+        _ => sweep(&[]),
+    }
+}
 
-            let phase_fn = &gen_phase_fn(a, ac, c, ca, offset);
-            for d in 0..cycle_len {
-                // isolate always, we keep that one
-                if a == 0 && c == 0 {
-                    continue;
-                }
+/// Replays a single trial, printing every event as JSON Lines
+///
+/// Usage: `trace a ac c ca offset moment who [a_test]`
+fn trace(args: &[String]) {
+    let params: Vec<u64> = args
+        .iter()
+        .take(7)
+        .map(|a| a.parse().expect("expected a number"))
+        .collect();
+    if params.len() < 7 {
+        eprintln!("usage: trace a ac c ca offset moment who [a_test]");
+        return;
+    }
+    let (a, ac, c, ca, offset) = (params[0], params[1], params[2], params[3], params[4]);
+    let testing = a_testing(args.get(7).is_some_and(|t| t == "true"));
+
+    let phase_fn = gen_phase_fn(a, ac, c, ca, offset).expect("the rotation has no days");
+    let mut trace = Trace::new();
+    run_trial(
+        params[5],
+        params[6],
+        &testing,
+        &Family::default(),
+        &phase_fn,
+        false,
+        Some(&mut trace),
+    );
+    trace
+        .write_json_lines(io::stdout().lock())
+        .expect("could not write trace");
+}
+
+/// Runs outbreaks over a contact graph read from an edge list, one line per outbreak
+///
+/// Usage: `network edges.txt seed [n]`
+fn network(args: &[String]) {
+    if args.len() < 2 {
+        eprintln!("usage: network edges.txt seed [n]");
+        return;
+    }
+    let file = File::open(&args[0]).expect("could not open edge list");
+    let network = ContactNetwork::from_edge_list(BufReader::new(file)).expect("invalid edge list");
+    let seed = args[1].parse().expect("expected a seed person");
+    let n = args
+        .get(2)
+        .map_or(1, |n| n.parse().expect("expected a number of runs"));
+
+    println!("run, final_size, attack_rate, setting, setting_attack_rate, detection");
+    for run in 0..n {
+        let outbreak = network.run(seed, 0).expect("invalid seed");
+        let attack_rate = outbreak.final_size as f64 / network.n_people() as f64;
+        let detection = outbreak
+            .detection
+            .map_or("NA".to_string(), |d| d.to_string());
+        let mut settings: Vec<_> = outbreak.attack_rates.iter().collect();
+        settings.sort_by(|a, b| a.0.cmp(b.0));
+        for (setting, rate) in settings {
+            println!(
+                "{}, {}, {}, {}, {}, {}",
+                run, outbreak.final_size, attack_rate, setting, rate, detection
+            );
+        }
+    }
+}
+
+/// Seeds each of A, B and C on every day of the cycle, following only the people they infect
+/// themselves, and prints the reproduction number, household secondary attack rate and offspring
+/// distribution of each
+///
+/// Usage: `secondary a ac c ca offset [a_test] [n]`
+fn secondary(args: &[String]) {
+    let params: Vec<u64> = args
+        .iter()
+        .take(5)
+        .map(|a| a.parse().expect("expected a number"))
+        .collect();
+    if params.len() < 5 {
+        eprintln!("usage: secondary a ac c ca offset [a_test] [n]");
+        return;
+    }
+    let (a, ac, c, ca, offset) = (params[0], params[1], params[2], params[3], params[4]);
+    let testing = a_testing(args.get(5).is_some_and(|t| t == "true"));
+    let n = args
+        .get(6)
+        .map_or(10_000, |n| n.parse().expect("expected a number of runs"));
+
+    let phase_fn = gen_phase_fn(a, ac, c, ca, offset).expect("the rotation has no days");
+    let family = Family::default();
+    let mut all = SecondaryCases::new();
+    println!("index, n, r, secondary_attack_rate, offspring_0, offspring_1, offspring_2");
+    for (who, name) in (1..4).zip(&["A", "B", "C"]) {
+        // B lives with both A and C, everyone B infects is a household member
+        let household = if who == 2 { 2 } else { 1 };
+        let mut cases = SecondaryCases::new();
+        for day in 0..a + ac + c + ca {
+            for _ in 0..n {
+                let (_, n_infected) =
+                    run_trial(day, who, &testing, &family, &phase_fn, true, None).res;
+                let offspring = n_infected as usize - 1;
+                cases.record(offspring, household, offspring);
+            }
+        }
+        print_secondary(name, &cases);
+        all += cases;
+    }
+    print_secondary("all", &all);
+}
+
+/// Who each line of the breakdown is about: A, B and C alone, then the homes of A and C, which B
+/// shares
+const GROUPS: [(&str, &[usize]); 5] = [
+    ("A", &[0]),
+    ("B", &[1]),
+    ("C", &[2]),
+    ("home_a", &[0, 1]),
+    ("home_c", &[1, 2]),
+];
+
+/// Seeds each of A, B and C on every day of the cycle and prints, for each source, how often every
+/// person and home got infected, how long they stayed unaware and how they found out. With
+/// `symptom_test`, everyone acting on symptoms gets a PCR test too
+///
+/// Usage: `breakdown a ac c ca offset [a_test] [n] [symptom_test]`
+fn breakdown(args: &[String]) {
+    let params: Vec<u64> = args
+        .iter()
+        .take(5)
+        .map(|a| a.parse().expect("expected a number"))
+        .collect();
+    if params.len() < 5 {
+        eprintln!("usage: breakdown a ac c ca offset [a_test] [n] [symptom_test]");
+        return;
+    }
+    let (a, ac, c, ca, offset) = (params[0], params[1], params[2], params[3], params[4]);
+    let testing = a_testing(args.get(5).is_some_and(|t| t == "true"));
+    let n = args
+        .get(6)
+        .map_or(10_000, |n| n.parse().expect("expected a number of runs"));
 
-                // if it's the weekend
-                if d % 7 == 5 || d % 7 == 6 {
-                    // no isolation on the weekend...
-                    if phase_fn(d) == Phase::Isolate {
-                        return;
-                    }
+    let phase_fn = gen_phase_fn(a, ac, c, ca, offset).expect("the rotation has no days");
+    let mut family = Family::default();
+    if args.get(7).is_some_and(|t| t == "true") {
+        family = family.with_symptom_tests();
+    }
+    println!(
+        "source, who, n, p_infected, mean_days_unaware, p_detected, p_detected_by_test, \
+         mean_detection_delay"
+    );
+    for (who, source) in (1..4).zip(&["A", "B", "C"]) {
+        let mut breakdowns = [Breakdown::default(); 5];
+        for day in 0..a + ac + c + ca {
+            for _ in 0..n {
+                let trial = run_trial(day, who, &testing, &family, &phase_fn, false, None);
+                for (breakdown, (_, members)) in breakdowns.iter_mut().zip(&GROUPS) {
+                    breakdown.record(&trial.outcomes, members);
                 }
             }
+        }
+        for (breakdown, (name, _)) in breakdowns.iter().zip(&GROUPS) {
+            let n = breakdown.n as f64;
+            let delay = if breakdown.detected > 0 {
+                format!(
+                    "{:.4}",
+                    breakdown.detection_delay as f64 / breakdown.detected as f64
+                )
+            } else {
+                "NA".to_string()
+            };
+            println!(
+                "{}, {}, {}, {:.4}, {:.4}, {:.4}, {:.4}, {}",
+                source,
+                name,
+                breakdown.n,
+                breakdown.infected as f64 / n,
+                breakdown.days_unaware as f64 / n,
+                breakdown.detected as f64 / n,
+                breakdown.by_test as f64 / n,
+                delay
+            );
+        }
+    }
+}
 
-            // A is getting tested
-            let a_test = true;
-            let outcomes = run_n(n, a_test, None, cycle_len, phase_fn);
-            for (res, n) in outcomes {
-                println!(
-                    "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
-                    a, ac, c, ca, offset, a_test, "NA", n, res.0, res.1
-                );
+/// Totals over trials for a person or a home
+#[derive(Debug, Clone, Copy, Default)]
+struct Breakdown {
+    n: u64,
+    infected: u64,
+    days_unaware: u64,
+    detected: u64,
+    by_test: u64,
+    detection_delay: u64,
+}
+
+impl Breakdown {
+    /// Adds a trial, a group counting as infected or detected as soon as any member is
+    fn record(&mut self, outcomes: &[PersonOutcome], members: &[usize]) {
+        let members: Vec<&PersonOutcome> = members.iter().map(|m| &outcomes[*m]).collect();
+        self.n += 1;
+        self.days_unaware += members.iter().map(|o| o.days_unaware).sum::<u64>();
+
+        let infected = members.iter().filter_map(|o| o.infected).min();
+        let detection = members
+            .iter()
+            .filter_map(|o| o.detection)
+            .min_by_key(|d| d.day);
+        if let Some(infected) = infected {
+            self.infected += 1;
+            if let Some(detection) = detection {
+                self.detected += 1;
+                self.by_test += matches!(
+                    detection.route,
+                    DetectionRoute::SymptomTest | DetectionRoute::ScheduledTest
+                ) as u64;
+                self.detection_delay += detection.day - infected;
             }
+        }
+    }
+}
 
-            // B is no getting tested
-            let a_test = false;
-            let outcomes = run_n(n, a_test, None, cycle_len, phase_fn);
-            for (res, n) in outcomes {
-                println!(
-                    "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
-                    a, ac, c, ca, offset, a_test, "NA", n, res.0, res.1
-                );
+impl AddAssign for Breakdown {
+    fn add_assign(&mut self, other: Breakdown) {
+        self.n += other.n;
+        self.infected += other.infected;
+        self.days_unaware += other.days_unaware;
+        self.detected += other.detected;
+        self.by_test += other.by_test;
+        self.detection_delay += other.detection_delay;
+    }
+}
+
+/// One line of secondary cases
+fn print_secondary(index: &str, cases: &SecondaryCases) {
+    let mut offspring = cases.offspring_distribution();
+    offspring.resize(3, 0.);
+    println!(
+        "{}, {}, {:.4}, {:.4}, {:.4}, {:.4}, {:.4}",
+        index,
+        cases.index_cases(),
+        cases.reproduction_number(),
+        cases.secondary_attack_rate(),
+        offspring[0],
+        offspring[1],
+        offspring[2]
+    );
+}
+
+/// Runs the mean-field model of a large population with the default adult parameters, printing
+/// the compartments every day
+///
+/// Usage: `seir population contact_rate [test_rate] [days]`, the test rate in tests per person
+/// per day
+fn seir(args: &[String]) {
+    if args.len() < 2 {
+        eprintln!("usage: seir population contact_rate [test_rate] [days]");
+        return;
+    }
+    let population: f64 = args[0].parse().expect("expected a population size");
+    let contact_rate = args[1].parse().expect("expected a contact rate");
+    let test_rate = args
+        .get(2)
+        .map_or(0., |t| t.parse().expect("expected a test rate"));
+    let days = args.get(3).map_or(HORIZON as u32, |d| {
+        d.parse().expect("expected a number of days")
+    });
+
+    let params = Disease::default().params(AgeGroup::Adult).clone();
+    let model = Seir::new(&params, contact_rate)
+        .expect("invalid parameters")
+        .with_testing(test_rate, TestKind::PCR)
+        .expect("invalid test rate");
+    eprintln!("r0: {}", model.r0());
+
+    println!("day, susceptible, exposed, presymptomatic, infectious, isolated, recovered");
+    for (day, s) in model
+        .run(SeirState::seeded(population, 1.), days)
+        .iter()
+        .enumerate()
+    {
+        println!(
+            "{}, {}, {}, {}, {}, {}, {}",
+            day, s.susceptible, s.exposed, s.presymptomatic, s.infectious, s.isolated, s.recovered
+        );
+    }
+}
+
+/// Searches rotation schedules for the Pareto front of expected infections against days unaware
+///
+/// Usage: `optimize [max_isolation_days] [min_days_each] [max_weeks] [n] [a_test]`
+fn optimize_schedules(args: &[String]) {
+    let arg = |i: usize, default: u64| {
+        args.get(i)
+            .map_or(default, |a| a.parse().expect("expected a number"))
+    };
+    let constraints = Constraints {
+        max_isolation_days: arg(0, 10),
+        weekend_isolation: false,
+        min_days_each: arg(1, 1),
+        max_weeks: arg(2, 2),
+    };
+    let n = arg(3, 1_000);
+    let a_test = args.get(4).is_some_and(|t| t == "true");
+    let testing = a_testing(a_test);
+
+    // cheap rounds first, to prune the clearly worse schedules
+    let rounds = [(n / 100).max(1), (n / 10).max(1), n];
+    let front = optimize(constraints.candidates(), &rounds, |schedule, n| {
+        evaluate(schedule, &testing, n)
+    });
+
+    println!(
+        "a, ac, c, ca, offset, a_test, n_infected, n_infected_se, days_unaware, days_unaware_se"
+    );
+    for (s, estimates) in front {
+        println!(
+            "{}, {}, {}, {}, {}, {}, {:.4}, {:.4}, {:.4}, {:.4}",
+            s.a,
+            s.ac,
+            s.c,
+            s.ca,
+            s.offset,
+            a_test,
+            estimates[0].mean,
+            estimates[0].stderr,
+            estimates[1].mean,
+            estimates[1].stderr
+        );
+    }
+}
+
+/// Ranks the weekly testing schedules within a test budget by how much they cut expected
+/// infections and days unaware, for a given rotation schedule
+///
+/// Usage: `testing a ac c ca offset [budget] [n]`
+fn optimize_testing(args: &[String]) {
+    let params: Vec<u64> = args
+        .iter()
+        .take(7)
+        .map(|a| a.parse().expect("expected a number"))
+        .collect();
+    if params.len() < 5 {
+        eprintln!("usage: testing a ac c ca offset [budget] [n]");
+        return;
+    }
+    let schedule = Schedule::new(params[0], params[1], params[2], params[3], params[4]);
+    let budget = params.get(5).map_or(2, |b| *b as usize);
+    let n = params.get(6).copied().unwrap_or(1_000);
+
+    let candidates =
+        TestingSchedule::within_budget(&[0, 1, 2], &[TestKind::PCR, TestKind::RAPID], budget);
+    let ranked = rank_testing(candidates, n, |testing, n| evaluate(&schedule, testing, n));
+
+    println!(
+        "tests, per_week, infections_cut, infections_cut_se, days_unaware_cut, days_unaware_cut_se"
+    );
+    for (testing, cuts) in ranked {
+        println!(
+            "{}, {}, {:.4}, {:.4}, {:.4}, {:.4}",
+            testing.describe(),
+            testing.per_week(),
+            cuts[0].mean,
+            cuts[0].stderr,
+            cuts[1].mean,
+            cuts[1].stderr
+        );
+    }
+}
+
+/// Expected infections and days unaware following the schedules, over every seed and start day
+fn evaluate(schedule: &Schedule, testing: &TestingSchedule, n: u64) -> Vec<Estimate> {
+    let phase_fn = schedule.phase_fn().expect("the schedule has no days");
+    let mut infected = Vec::new();
+    let mut unaware = Vec::new();
+    for day in 0..schedule.cycle_len() {
+        for source in 1..4 {
+            for _ in 0..n {
+                let (days_unaware, n_infected) = run_trial(
+                    day,
+                    source,
+                    testing,
+                    &Family::default(),
+                    &phase_fn,
+                    false,
+                    None,
+                )
+                .res;
+                infected.push(n_infected as f64);
+                unaware.push(days_unaware as f64);
             }
+        }
+    }
+    vec![
+        Estimate::from_samples(&infected),
+        Estimate::from_samples(&unaware),
+    ]
+}
+
+/// A getting a test on days 1 and 4 of every week, or nobody testing
+fn a_testing(a_test: bool) -> TestingSchedule {
+    if !a_test {
+        return TestingSchedule::default();
+    }
+    TestingSchedule::new(
+        [1, 4]
+            .iter()
+            .map(|weekday| ScheduledTest {
+                person: 0,
+                weekday: *weekday,
+                kind: TestKind::PCR,
+            })
+            .collect(),
+    )
+}
 
-            /*
-            for b_test in 0..cycle_len {
-                let outcomes = run_n(n, Some(b_test), cycle_len, phase_fn);
-                for (res, n) in outcomes {
+/// Does B isolate on a weekend day? Isolating all the time is the exception, we keep that one
+fn isolates_on_weekends(phase_desc: &(u64, u64, u64, u64, u64)) -> bool {
+    let (a, ac, c, ca, offset) = *phase_desc;
+    if a == 0 && c == 0 {
+        return false;
+    }
+    let phase_fn = gen_phase_fn(a, ac, c, ca, offset).expect("the rotation has no days");
+    (0..a + ac + c + ca).any(|d| (d % 7 == 5 || d % 7 == 6) && phase_fn(d) == Phase::Isolate)
+}
+
+/// For every schedule of the sweep, with and without A testing, prints how long outbreaks took
+/// to get noticed and what gave them away, one line per route and number of days since the
+/// seed exposure. With `symptom_test`, everyone acting on symptoms gets a PCR test too
+///
+/// Usage: `detection [n] [symptom_test]`
+fn detection(args: &[String]) {
+    let n = args
+        .first()
+        .map_or(10_000, |n| n.parse().expect("expected a number of runs"));
+    let mut family = Family::default();
+    if args.get(1).is_some_and(|t| t == "true") {
+        family = family.with_symptom_tests();
+    }
+
+    println!("a, ac, c, ca, offset, a_test, route, days, n");
+    gen_phases()
+        .par_iter()
+        .filter(|phase_desc| !isolates_on_weekends(phase_desc))
+        .for_each(|phase_desc| {
+            let (a, ac, c, ca, offset) = *phase_desc;
+            for a_test in &[true, false] {
+                let detections = detections(phase_desc, &a_testing(*a_test), &family, n);
+                for (detection, count) in detections {
+                    let (route, days) = match detection {
+                        Some((route, days)) => (route.name(), days.to_string()),
+                        None => ("none", "NA".to_string()),
+                    };
                     println!(
                         "{}, {}, {}, {}, {}, {}, {}, {}, {}",
-                        a, ac, c, ca, offset, b_test, n, res.0, res.1
+                        a, ac, c, ca, offset, a_test, route, days, count
                     );
                 }
             }
-            */
-        })
-        .collect::<()>();
+        });
+}
+
+/// Number of trials noticed through each route after each number of days, or never noticed,
+/// over every start day and source of the schedule
+fn detections(
+    phase_desc: &(u64, u64, u64, u64, u64),
+    testing: &TestingSchedule,
+    family: &Family,
+    n: u64,
+) -> BTreeMap<Option<(DetectionRoute, u64)>, u64> {
+    let (a, ac, c, ca, offset) = *phase_desc;
+    let phase_fn = gen_phase_fn(a, ac, c, ca, offset).expect("the rotation has no days");
+    let mut detections = BTreeMap::new();
+    for day in 0..a + ac + c + ca {
+        for source in 1..4 {
+            for _ in 0..n {
+                let trial = run_trial(day, source, testing, family, &phase_fn, false, None);
+                let detection = trial.detection.map(|d| (d.route, d.day));
+                *detections.entry(detection).or_insert(0) += 1;
+            }
+        }
+    }
+    detections
+}
+
+/// Header of the sweep's output, the totals of every person and home of `GROUPS` coming last
+fn sweep_header() -> String {
+    let mut header = "a, ac, c, ca, offset, a_test, b_test, source, n, tot_days_unaware, \
+                      n_infected, expected_hospitalized, expected_icu, expected_deaths, \
+                      generation_1, generation_2, mean_serial_interval"
+        .to_string();
+    for (name, _) in &GROUPS {
+        let name = name.to_lowercase();
+        for column in &[
+            "infected",
+            "days_unaware",
+            "detected",
+            "detected_by_test",
+            "detection_delay",
+        ] {
+            header += &format!(", {}_{}", name, column);
+        }
+    }
+    header
+}
+
+/// Runs every schedule with and without A testing. Given a directory rather than `-` for stdout,
+/// each scenario goes to its own file there and scenarios already in it are skipped, so a sweep
+/// that died can simply be started again
+///
+/// Each line is about the trials seeded with one of A, B and C that ended the same way, and
+/// carries, for every person and home, in how many of them it got infected and detected, so that
+/// for example
+/// summing `c_infected` and `n` over the lines with source A gives the probability C gets
+/// infected when A is the source. Results only depend on the seed, not on the number of threads.
+///
+/// Usage: `sweep [out_dir|-] [n] [seed]`
+fn sweep(args: &[String]) {
+    let checkpoints = args
+        .first()
+        .filter(|dir| *dir != "-")
+        .map(|dir| Checkpoints::new(dir).expect("could not create output directory"));
+    let n = args
+        .get(1)
+        .map_or(100_000, |n| n.parse().expect("expected a number of runs"));
+    let seed: u64 = args
+        .get(2)
+        .map_or(0, |s| s.parse().expect("expected a seed"));
+
+    if checkpoints.is_none() {
+        println!("{}", sweep_header());
+    }
+    //for (a, ac, c, ca) in gen_phases() {
+    let mut scenarios = Vec::new();
+    for phase_desc in gen_phases()
+        .into_iter()
+        .filter(|phase_desc| !isolates_on_weekends(phase_desc))
+    {
+        let (a, ac, c, ca, offset) = phase_desc;
+
+        // with A getting tested, then without
+        for a_test in &[true, false] {
+            let name = format!(
+                "a={} ac={} c={} ca={} offset={} a_test={} n={} seed={}",
+                a, ac, c, ca, offset, a_test, n, seed
+            );
+            if checkpoints.as_ref().is_some_and(|c| c.is_done(&name)) {
+                eprintln!("skipping {}", name);
+                continue;
+            }
+            scenarios.push(Scenario {
+                phase_fn: gen_phase_fn(a, ac, c, ca, offset).expect("the rotation has no days"),
+                cycle_len: a + ac + c + ca,
+                testing: a_testing(*a_test),
+                seed: Checkpoints::hash(&name),
+                name,
+                phase_desc,
+                a_test: *a_test,
+            });
+        }
+
+        /*
+        for b_test in 0..cycle_len {
+            let outcomes = run_n(n, Some(b_test), cycle_len, phase_fn);
+            for (res, n) in outcomes {
+                println!(
+                    "{}, {}, {}, {}, {}, {}, {}, {}, {}",
+                    a, ac, c, ca, offset, b_test, n, res.0, res.1
+                );
+            }
+        }
+        */
+    }
+
+    let results: Vec<Mutex<Option<String>>> = scenarios.iter().map(|_| Mutex::new(None)).collect();
+    run_n(n, &scenarios, |s, outcomes| {
+        let scenario = &scenarios[s];
+        let lines = format_outcomes(&scenario.phase_desc, scenario.a_test, &outcomes);
+        match &checkpoints {
+            Some(checkpoints) => checkpoints
+                .save(&scenario.name, &format!("{}\n{}", sweep_header(), lines))
+                .expect("could not save results"),
+            None => *results[s].lock().expect("a scenario panicked") = Some(lines),
+        }
+    });
+    for lines in results
+        .into_iter()
+        .filter_map(|r| r.into_inner().ok().flatten())
+    {
+        print!("{}", lines);
+    }
+}
+
+/// A scenario of the sweep, still to be run
+struct Scenario {
+    name: String,
+    phase_desc: (u64, u64, u64, u64, u64),
+    a_test: bool,
+    testing: TestingSchedule,
+    cycle_len: u64,
+    phase_fn: Box<dyn Fn(u64) -> Phase + Send + Sync>,
+    seed: u64,
 }
 
 fn gen_phases() -> Vec<(u64, u64, u64, u64, u64)> {
@@ -111,89 +685,402 @@ fn gen_phases() -> Vec<(u64, u64, u64, u64, u64)> {
     phases
 }
 
-fn run_n(
+/// Trials run back to back on the same random stream
+const CHUNK: u64 = 1_000;
+
+/// Start day, source and number of trials of a chunk
+type Chunk = (u64, u64, u64);
+
+/// The chunks running every start day and source n times, in a stable order
+fn chunks(n: u64, cycle_len: u64) -> Vec<Chunk> {
+    (0..cycle_len)
+        .flat_map(|day| (1..4).map(move |source| (day, source)))
+        .flat_map(|(day, source)| {
+            (0..n)
+                .step_by(CHUNK as usize)
+                .map(move |start| (day, source, min(CHUNK, n - start)))
+        })
+        .collect()
+}
+
+/// Number of chunks `chunks` splits the trials into
+fn chunks_len(n: u64, cycle_len: u64) -> usize {
+    (cycle_len * 3 * n.div_ceil(CHUNK)) as usize
+}
+
+/// Runs the trials of a chunk on the random stream of the given seed
+fn run_chunk(
+    chunk: &Chunk,
+    testing: &TestingSchedule,
+    phase_fn: &(dyn Fn(u64) -> Phase + Sync),
+    seed: u64,
+) -> BTreeMap<Key, Outcomes> {
+    let (day, source, trials) = *chunk;
+    reseed(seed);
+    let family = Family::default();
+    let mut res: BTreeMap<Key, Outcomes> = BTreeMap::new();
+    for _ in 0..trials {
+        let trial = run_trial(day, source, testing, &family, phase_fn, false, None);
+        res.entry((source, trial.res)).or_default().record(&trial);
+    }
+    res
+}
+
+/// Adds up the outcomes of chunks, in the order given
+fn merge(partials: impl Iterator<Item = BTreeMap<Key, Outcomes>>) -> BTreeMap<Key, Outcomes> {
+    let mut res: BTreeMap<Key, Outcomes> = BTreeMap::new();
+    for partial in partials {
+        for (run, outcomes) in partial {
+            *res.entry(run).or_default() += outcomes;
+        }
+    }
+    res
+}
+
+/// Runs every start day and source of every scenario n times, in chunks spread over every
+/// thread, and hands each scenario's outcomes to `done` as soon as its last chunk is in. Each
+/// chunk gets its own random stream drawn from the scenario's seed and chunks are merged in order,
+/// so the same seed always gives the same outcomes
+fn run_n(n: u64, scenarios: &[Scenario], done: impl Fn(usize, BTreeMap<Key, Outcomes>) + Sync) {
+    let chunks: Vec<(usize, usize, Chunk)> = scenarios
+        .iter()
+        .enumerate()
+        .flat_map(|(s, scenario)| {
+            chunks(n, scenario.cycle_len)
+                .into_iter()
+                .enumerate()
+                .map(move |(i, chunk)| (s, i, chunk))
+        })
+        .collect();
+    let partials: Vec<Mutex<Vec<_>>> = scenarios
+        .iter()
+        .map(|s| Mutex::new((0..chunks_len(n, s.cycle_len)).map(|_| None).collect()))
+        .collect();
+
+    chunks.par_iter().for_each(|(s, i, chunk)| {
+        let scenario = &scenarios[*s];
+        let partial = run_chunk(
+            chunk,
+            &scenario.testing,
+            &scenario.phase_fn,
+            stream_seed(scenario.seed, *i as u64),
+        );
+
+        let mut slots = partials[*s].lock().expect("a chunk panicked");
+        slots[*i] = Some(partial);
+        if slots.iter().all(Option::is_some) {
+            let outcomes = merge(slots.drain(..).flatten());
+            drop(slots);
+            done(*s, outcomes);
+        }
+    });
+}
+
+/// Trials with the same source that ended the same way
+#[derive(Debug, Default)]
+struct Outcomes {
     n: u64,
+    severe: SevereRisk,
+    generations: Generations,
+    /// Totals of every person and home of `GROUPS`
+    breakdowns: [Breakdown; 5],
+}
+
+impl Outcomes {
+    /// Adds a trial
+    fn record(&mut self, trial: &Trial) {
+        self.n += 1;
+        self.severe += trial.severe;
+        self.generations.record(&trial.people);
+        for (breakdown, (_, members)) in self.breakdowns.iter_mut().zip(&GROUPS) {
+            breakdown.record(&trial.outcomes, members);
+        }
+    }
+}
+
+impl AddAssign for Outcomes {
+    fn add_assign(&mut self, other: Outcomes) {
+        self.n += other.n;
+        self.severe += other.severe;
+        self.generations += other.generations;
+        for (breakdown, other) in self.breakdowns.iter_mut().zip(other.breakdowns) {
+            *breakdown += other;
+        }
+    }
+}
+
+/// A line of the sweep for each way the trials ended
+fn format_outcomes(
+    phase_desc: &(u64, u64, u64, u64, u64),
     a_test: bool,
-    b_test: Option<u64>,
-    cycle_len: u64,
-    phase_fn: &dyn Fn(u64) -> Phase,
-) -> HashMap<Res, u64> {
-    let mut res = HashMap::new();
+    outcomes: &BTreeMap<Key, Outcomes>,
+) -> String {
+    let (a, ac, c, ca, offset) = *phase_desc;
+    let mut lines = String::new();
+    for ((source, res), outcome) in outcomes {
+        let n = outcome.n as f64;
+        let serial_interval = outcome
+            .generations
+            .mean_serial_interval()
+            .map_or("NA".to_string(), |s| format!("{:.4}", s));
+        lines += &format!(
+            "{}, {}, {}, {}, {}, {}, NA, {}, {}, {}, {}, {:.6}, {:.6}, {:.6}, {:.6}, {:.6}, {}",
+            a,
+            ac,
+            c,
+            ca,
+            offset,
+            a_test,
+            GROUPS[*source as usize - 1].0,
+            outcome.n,
+            res.0,
+            res.1,
+            outcome.severe.hospitalization / n,
+            outcome.severe.icu / n,
+            outcome.severe.death / n,
+            outcome.generations.mean_infections(1),
+            outcome.generations.mean_infections(2),
+            serial_interval
+        );
+        for b in &outcome.breakdowns {
+            lines += &format!(
+                ", {}, {}, {}, {}, {}",
+                b.infected, b.days_unaware, b.detected, b.by_test, b.detection_delay
+            );
+        }
+        lines += "\n";
+    }
+    lines
+}
 
-    for day in 0..cycle_len {
-        for source in 1..4 {
-            for _ in 0..n {
-                let run = run_trial(day, source, a_test, b_test, cycle_len, phase_fn);
-                let cur = res.get(&run).or_else(|| Some(&0)).unwrap() + 1;
-                res.insert(run, cur);
-            }
+/// How a single trial ended
+#[derive(Debug)]
+struct Trial {
+    res: Res,
+    severe: SevereRisk,
+    outcomes: [PersonOutcome; 3],
+    people: [Person; 3],
+    /// First detection, counted in days since the seed exposure
+    detection: Option<Detection>,
+}
+
+/// How a single trial went for one of A, B and C
+#[derive(Debug, Clone, Copy)]
+struct PersonOutcome {
+    infected: Option<u64>,
+    days_unaware: u64,
+    detection: Option<Detection>,
+}
+
+/// Who A, B and C are
+#[derive(Debug, Clone)]
+struct Family {
+    ages: [AgeGroup; 3],
+    adherence: [Adherence; 3],
+    symptoms: [SymptomModel; 3],
+    vaccinated: [bool; 3],
+    disease: Disease,
+    isolation_policy: IsolationPolicy,
+}
+
+impl Default for Family {
+    /// B is a child moving between the homes of two adults, everyone following the rules, acting
+    /// on symptoms the day they start and isolating indefinitely
+    fn default() -> Self {
+        Family {
+            ages: [AgeGroup::Adult, AgeGroup::Child, AgeGroup::Adult],
+            adherence: [Adherence::PERFECT; 3],
+            symptoms: [SymptomModel::default(); 3],
+            vaccinated: [false; 3],
+            disease: Disease::age_structured(),
+            isolation_policy: IsolationPolicy::default(),
         }
     }
-    res
 }
 
-/// Runs a single experiment
+impl Family {
+    /// Everyone acting on symptoms gets a PCR test too
+    fn with_symptom_tests(mut self) -> Family {
+        self.symptoms = [SymptomModel {
+            test: Some(TestKind::PCR),
+            ..SymptomModel::default()
+        }; 3];
+        self
+    }
+
+    /// A, B and C, not infected yet
+    fn people(&self) -> [Person; 3] {
+        [0, 1, 2].map(|i| {
+            Person::new()
+                .with_id(i)
+                .with_isolation_policy(self.isolation_policy)
+                .with_age(self.ages[i], &self.disease)
+                .expect("invalid disease parameters")
+                .with_adherence(self.adherence[i])
+                .expect("invalid adherence")
+                .with_symptoms(self.symptoms[i])
+                .expect("invalid symptom model")
+                .with_vaccination(self.vaccinated[i])
+        })
+    }
+}
+
+/// Runs a single experiment. In the first generation only, nobody but `who` passes the virus on
 fn run_trial(
     moment: u64,
     who: u64,
-    a_test: bool,
-    b_test: Option<u64>,
-    cycle_len: u64,
+    testing: &TestingSchedule,
+    family: &Family,
     phase_fn: &dyn Fn(u64) -> Phase,
-) -> Res {
-    let mut a = Person::new();
-    let mut b = Person::new();
-    let mut c = Person::new();
+    first_generation: bool,
+    trace: Option<&mut Trace>,
+) -> Trial {
+    let agenda = Agenda::new();
+    run_trial_on(
+        moment,
+        who,
+        testing,
+        family,
+        phase_fn,
+        first_generation,
+        trace,
+        agenda,
+    )
+}
 
+/// Same as `run_trial`, waking up on the days the given agenda says rather than only on those
+/// where something can happen
+#[allow(clippy::too_many_arguments)]
+fn run_trial_on(
+    moment: u64,
+    who: u64,
+    testing: &TestingSchedule,
+    family: &Family,
+    phase_fn: &dyn Fn(u64) -> Phase,
+    first_generation: bool,
+    mut trace: Option<&mut Trace>,
+    mut agenda: Agenda,
+) -> Trial {
+    let mut people = family.people();
     match who {
-        1 => a.expose(moment), //, format!("A.{:}", moment)),
-        2 => b.expose(moment), //, format!("B.{:}", moment)),
-        3 => c.expose(moment), //, format!("C.{:}", moment)),
+        1 => people[0].expose(moment), //, format!("A.{:}", moment)),
+        2 => people[1].expose(moment), //, format!("B.{:}", moment)),
+        3 => people[2].expose(moment), //, format!("C.{:}", moment)),
         _ => unreachable!(),
     }
 
-    let mut max_day = 0;
-    for day in moment..300 {
-        max_day = day;
+    // A lives in household 0, C in household 1, B moves between them and is alone in 2
+    let scope = IsolationScope::Household;
+    let mut households = Households::new(vec![0, 2, 1]);
+    let mut current = Phase::Isolate;
 
-        // someone is isolating, stop sim
-        if a.is_isolating(day) || b.is_isolating(day) || c.is_isolating(day) {
-            break;
-        }
+    // only run the days where something can happen
+    agenda.schedule(moment);
+    let mut max_day = moment;
+    while let Some(day) = agenda.next() {
+        max_day = min(day, HORIZON - 1);
 
         // everyone has recovered, stop
-        if a.has_recovered(day) && b.has_recovered(day) && c.has_recovered(day) {
+        if day >= HORIZON || people.iter().all(|p| p.has_recovered(day)) {
             break;
         }
 
-        match day % 7 {
-            1 | 4 => {
-                if a_test {
-                    a.test(day, 1);
-                }
+        for p in &mut people {
+            p.update_isolation(day);
+        }
+
+        for t in testing.tests_on(day) {
+            if people[t.person].takes_scheduled_test() {
+                people[t.person]
+                    .test_with(day, t.kind)
+                    .expect("invalid scheduled test");
             }
-            0 | 3 => {}
-            _ => {}
         }
 
-        if b_test.is_some() && day % cycle_len == b_test.unwrap() {
-            b.test(day, 2);
+        // handovers are skipped while either household is isolating
+        let isolating = households.isolating(&people, scope, day);
+        current = react_phase(phase_fn(day), current, isolating[0], isolating[2]);
+        households.move_person(
+            1,
+            match current {
+                Phase::A => 0,
+                Phase::C => 1,
+                Phase::Isolate => 2,
+            },
+        );
+
+        // B may have just joined an isolating household, or left one
+        let isolating = households.isolating(&people, scope, day);
+        let other = match current {
+            Phase::A => Some(0),
+            Phase::C => Some(2),
+            Phase::Isolate => None,
+        };
+        // breaking isolation is only drawn for meetings that can pass the virus on, or that get
+        // traced, so that idle days stay idle
+        let met = other.filter(|o| {
+            (trace.is_some() || people[1].is_contagious(day) || people[*o].is_contagious(day))
+                && households.can_meet(&people, &isolating, scope, 1, *o)
+        });
+        let seed = who as usize - 1;
+        let spreading = met.filter(|o| !first_generation || seed == 1 || seed == *o);
+
+        let [a, b, c] = &mut people;
+        match spreading {
+            Some(0) => b.interact(day, a),
+            // a.interact(day, &mut z);
+            Some(_) => b.interact(day, c),
+            None => {}
         }
 
-        match phase_fn(day) {
-            Phase::A => b.interact(day, &mut a),
-            Phase::C => {
-                // a.interact(day, &mut z);
-                b.interact(day, &mut c);
+        if let Some(trace) = trace.as_mut() {
+            if let Some(other) = met {
+                trace.record(Event::Interaction {
+                    time: day,
+                    a: 1,
+                    b: other,
+                });
+            }
+            for (i, p) in people.iter().enumerate() {
+                trace.observe(day, i, p);
             }
-            Phase::Isolate => {}
+        }
+
+        // B meets someone every day, traces keep even the harmless meetings
+        if trace.is_some() || people.iter().any(|p| p.is_contagious(day)) {
+            agenda.schedule(day + 1);
+        }
+        agenda.schedule_people(day, &people);
+        if let Some(next) = testing.next_test(day) {
+            agenda.schedule(next);
         }
     }
 
-    (
+    let mut severe = SevereRisk::default();
+    for p in &people {
+        severe += p.severe_risk();
+    }
+    let [a, b, c] = &people;
+    let res = (
         a.days_unaware(max_day) + b.days_unaware(max_day) + c.days_unaware(max_day),
         a.was_sick(max_day) as u64 + b.was_sick(max_day) as u64 + c.was_sick(max_day) as u64,
-    )
+    );
+    let outcomes = [a, b, c].map(|p| PersonOutcome {
+        infected: p.infection_date(),
+        days_unaware: p.days_unaware(max_day),
+        detection: p.detection(max_day),
+    });
+    let detection = first_detection(&people, max_day).map(|d| Detection {
+        day: d.day - moment,
+        ..d
+    });
+    Trial {
+        res,
+        severe,
+        outcomes,
+        people,
+        detection,
+    }
 }
 
 /*
@@ -205,3 +1092,232 @@ fn get_source(p: &Person) -> String {
     }
 }
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn agenda_matches_daily_loop() {
+        let testing = a_testing(true);
+        let sloppy = Adherence {
+            skip_test: 0.3,
+            break_isolation: 0.5,
+            unreported: 0.2,
+        };
+        let families = [
+            Family::default(),
+            Family {
+                adherence: [sloppy; 3],
+                ..Family::default()
+            },
+        ];
+        let phase_fn = gen_phase_fn(7, 0, 7, 0, 0).unwrap();
+        for family in &families {
+            for seed in 0..100 {
+                for who in 1..4 {
+                    let run = |agenda| {
+                        reseed(seed);
+                        let trial = run_trial_on(
+                            seed % 14,
+                            who,
+                            &testing,
+                            family,
+                            &phase_fn,
+                            false,
+                            None,
+                            agenda,
+                        );
+                        format!("{:?}", trial)
+                    };
+                    assert_eq!(run(Agenda::new()), run(Agenda::daily()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn traced_sources() {
+        let testing = a_testing(false);
+        let phase_fn = gen_phase_fn(1, 0, 1, 0, 0).unwrap();
+        for seed in 0..50 {
+            reseed(seed);
+            let mut trace = Trace::new();
+            run_trial(
+                0,
+                2,
+                &testing,
+                &Family::default(),
+                &phase_fn,
+                false,
+                Some(&mut trace),
+            );
+
+            // B is the seed, A and C can only catch it from B
+            for event in trace.events() {
+                if let Event::Exposure { person, source, .. } = *event {
+                    assert_eq!(source, if person == 1 { None } else { Some(1) });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn family_symptoms() {
+        let testing = TestingSchedule::default();
+        let phase_fn = gen_phase_fn(7, 0, 7, 0, 0).unwrap();
+        let ignoring = Family {
+            symptoms: [SymptomModel {
+                ignore: 1.,
+                ..SymptomModel::default()
+            }; 3],
+            ..Family::default()
+        };
+        let mut noticed = 0;
+        for seed in 0..50 {
+            reseed(seed);
+            let trial = run_trial(seed % 14, 1, &testing, &ignoring, &phase_fn, false, None);
+            assert!(trial.detection.is_none());
+
+            reseed(seed);
+            let trial = run_trial(
+                seed % 14,
+                1,
+                &testing,
+                &Family::default(),
+                &phase_fn,
+                false,
+                None,
+            );
+            noticed += trial.detection.is_some() as usize;
+        }
+        assert!(noticed > 0);
+    }
+
+    #[test]
+    fn symptom_test_route() {
+        let testing = TestingSchedule::default();
+        let tested = Family::default().with_symptom_tests();
+        let routes = |family: &Family| -> Vec<DetectionRoute> {
+            detections(&(7, 0, 7, 0, 0), &testing, family, 5)
+                .keys()
+                .filter_map(|d| d.map(|(route, _)| route))
+                .collect()
+        };
+
+        // without tests nobody is found out but through symptoms, tested or not
+        reseed(1);
+        let untested = routes(&Family::default());
+        assert!(!untested.is_empty());
+        assert!(untested.iter().all(|r| *r == DetectionRoute::Symptoms));
+        reseed(1);
+        let tested = routes(&tested);
+        assert!(!tested.is_empty());
+        assert!(tested.iter().all(|r| *r == DetectionRoute::SymptomTest));
+    }
+
+    #[test]
+    fn family_isolation_policy() {
+        let testing = a_testing(true);
+        let phase_fn = gen_phase_fn(7, 0, 7, 0, 0).unwrap();
+        let released = Family {
+            isolation_policy: IsolationPolicy::Fixed(10),
+            ..Family::default()
+        };
+        let mut detected = 0;
+        for seed in 0..50 {
+            for family in [&Family::default(), &released] {
+                reseed(seed);
+                let trial = run_trial(seed % 14, 1, &testing, family, &phase_fn, false, None);
+                for person in trial
+                    .people
+                    .iter()
+                    .filter(|p| p.detection(HORIZON).is_some())
+                {
+                    detected += 1;
+                    assert_eq!(
+                        person.is_isolating(HORIZON - 1),
+                        family.isolation_policy == IsolationPolicy::Indefinite
+                    );
+                }
+            }
+        }
+        assert!(detected > 0);
+    }
+
+    #[test]
+    fn sweep_by_source() {
+        let phase_fn = gen_phase_fn(1, 0, 1, 0, 0).unwrap();
+        let testing = a_testing(false);
+        let outcomes =
+            merge((1..4).map(|source| run_chunk(&(0, source, 500), &testing, &phase_fn, 7)));
+
+        let header = sweep_header();
+        let lines = format_outcomes(&(1, 0, 1, 0, 0), false, &outcomes);
+        for line in lines.lines() {
+            assert_eq!(line.split(", ").count(), header.split(", ").count());
+        }
+        let column = |name: &str| header.split(", ").position(|c| c == name).unwrap();
+        let (source, n, c_infected) = (column("source"), column("n"), column("c_infected"));
+
+        // the source is always infected, and C never more often than their home
+        let mut by_source = BTreeMap::new();
+        for ((source, _), outcome) in &outcomes {
+            let seed = &outcome.breakdowns[*source as usize - 1];
+            assert_eq!(seed.infected, outcome.n);
+            assert!(outcome.breakdowns[2].infected <= outcome.breakdowns[4].infected);
+            *by_source.entry(*source).or_insert(0) += outcome.n;
+        }
+        assert_eq!(by_source.values().collect::<Vec<_>>(), vec![&500; 3]);
+
+        // P(C infected | A is the source), back from the lines
+        let (mut trials, mut infected) = (0, 0);
+        for line in lines.lines() {
+            let values: Vec<&str> = line.split(", ").collect();
+            if values[source] == "A" {
+                trials += values[n].parse::<u64>().unwrap();
+                infected += values[c_infected].parse::<u64>().unwrap();
+            }
+        }
+        assert_eq!(trials, 500);
+        assert!(0 < infected && infected < trials, "{}", infected);
+    }
+
+    #[test]
+    fn run_n_ignores_threads() {
+        let scenarios: Vec<Scenario> = [(1, 0, 1, 0, 0), (1, 1, 1, 0, 1)]
+            .iter()
+            .map(|phase_desc| {
+                let (a, ac, c, ca, offset) = *phase_desc;
+                Scenario {
+                    name: format!("{:?}", phase_desc),
+                    phase_desc: *phase_desc,
+                    a_test: true,
+                    testing: a_testing(true),
+                    cycle_len: a + ac + c + ca,
+                    phase_fn: gen_phase_fn(a, ac, c, ca, offset).unwrap(),
+                    seed: 7,
+                }
+            })
+            .collect();
+        let run = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let results: Vec<Mutex<String>> = scenarios.iter().map(|_| Mutex::default()).collect();
+            pool.install(|| {
+                run_n(CHUNK + 10, &scenarios, |s, outcomes| {
+                    *results[s].lock().unwrap() = format!("{:?}", outcomes);
+                })
+            });
+            results
+                .into_iter()
+                .map(|r| r.into_inner().unwrap())
+                .collect::<Vec<String>>()
+        };
+        let single = run(1);
+        assert!(single.iter().all(|r| !r.is_empty()));
+        assert_eq!(single, run(4));
+    }
+}
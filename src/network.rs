@@ -0,0 +1,408 @@
+//! Outbreaks over an arbitrary contact graph
+
+use crate::{rng, Agenda, Error, IsolationPolicy, Person, Time, HORIZON};
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+/// When the two people of a contact meet
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContactSchedule {
+    /// Every day
+    Daily,
+
+    /// Every `period` days, starting on day `offset`
+    Every {
+        /// Days between two meetings
+        period: Time,
+        /// Day of the first meeting in the period
+        offset: Time,
+    },
+
+    /// On the given days of a cycle, e.g. `period` 7 for weekly schedules
+    Days {
+        /// Length of the cycle
+        period: Time,
+        /// Days of the cycle on which they meet
+        days: Vec<Time>,
+    },
+
+    /// Meeting at random, with the given probability each day
+    Random(f64),
+}
+
+impl ContactSchedule {
+    /// Do they meet on this day?
+    fn meets(&self, day: Time) -> bool {
+        match self {
+            ContactSchedule::Daily => true,
+            ContactSchedule::Every { period, offset } => day % period == offset % period,
+            ContactSchedule::Days { period, days } => days.contains(&(day % period)),
+            ContactSchedule::Random(p) => rng().gen_bool(*p),
+        }
+    }
+}
+
+/// An edge of the contact graph
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    a: usize,
+    b: usize,
+    weight: f64,
+    schedule: ContactSchedule,
+    setting: String,
+}
+
+impl Contact {
+    /// Creates a contact between `a` and `b`, passing the virus with probability `weight` each
+    /// time they meet. The weight and the probability of random meetings have to be
+    /// probabilities, and schedules need a period of at least one day
+    pub fn new(
+        a: usize,
+        b: usize,
+        weight: f64,
+        schedule: ContactSchedule,
+        setting: &str,
+    ) -> Result<Contact, Error> {
+        let valid = match &schedule {
+            ContactSchedule::Daily => true,
+            ContactSchedule::Every { period, .. } | ContactSchedule::Days { period, .. } => {
+                *period > 0
+            }
+            ContactSchedule::Random(p) => (0. ..=1.).contains(p),
+        };
+        if !valid {
+            return Err(Error::InvalidParameter(format!(
+                "contact schedule {:?}",
+                schedule
+            )));
+        }
+        if !(0. ..=1.).contains(&weight) {
+            return Err(Error::InvalidParameter(format!(
+                "contact weight of {}",
+                weight
+            )));
+        }
+
+        Ok(Contact {
+            a,
+            b,
+            weight,
+            schedule,
+            setting: setting.to_string(),
+        })
+    }
+}
+
+/// A contact graph, people are the nodes and contacts the edges
+#[derive(Debug, Clone, Default)]
+pub struct ContactNetwork {
+    n_people: usize,
+    contacts: Vec<Contact>,
+    isolation_policy: IsolationPolicy,
+}
+
+/// Summary of a single outbreak over a network
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outbreak {
+    /// Number of people infected, including the seed
+    pub final_size: usize,
+
+    /// For each setting, the share of the people in contact through that setting who got
+    /// infected there
+    pub attack_rates: HashMap<String, f64>,
+
+    /// Days from the seed exposure to the first person isolating, if anyone ever did
+    pub detection: Option<Time>,
+}
+
+impl ContactNetwork {
+    /// Creates an empty network
+    pub fn new() -> ContactNetwork {
+        ContactNetwork::default()
+    }
+
+    /// Sets the rules deciding when people stop isolating, by default they never do
+    pub fn with_isolation_policy(mut self, isolation_policy: IsolationPolicy) -> ContactNetwork {
+        self.isolation_policy = isolation_policy;
+        self
+    }
+
+    /// Adds a contact, growing the network to include both people
+    pub fn add_contact(&mut self, contact: Contact) {
+        self.n_people = self.n_people.max(contact.a + 1).max(contact.b + 1);
+        self.contacts.push(contact);
+    }
+
+    /// Number of people in the network
+    pub fn n_people(&self) -> usize {
+        self.n_people
+    }
+
+    /// Reads an edge list, one contact per line: `a b weight setting [schedule]`
+    ///
+    /// The schedule is empty for daily contacts, `every <period> [offset]`,
+    /// `days <period> <day>...` or `random <probability>`. Blank lines and lines starting with
+    /// `#` are ignored.
+    pub fn from_edge_list<R: BufRead>(reader: R) -> io::Result<ContactNetwork> {
+        let mut network = ContactNetwork::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid contact on line {}: {}", i + 1, line),
+                )
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                return Err(invalid());
+            }
+            let a = fields[0].parse().map_err(|_| invalid())?;
+            let b = fields[1].parse().map_err(|_| invalid())?;
+            let weight = fields[2].parse().map_err(|_| invalid())?;
+            let numbers = fields[5.min(fields.len())..]
+                .iter()
+                .map(|f| f.parse::<Time>())
+                .collect::<Result<Vec<Time>, _>>();
+
+            let schedule = match (fields.get(4).copied(), numbers) {
+                (None, _) => ContactSchedule::Daily,
+                (Some("every"), Ok(n)) if !n.is_empty() => ContactSchedule::Every {
+                    period: n[0],
+                    offset: n.get(1).copied().unwrap_or(0),
+                },
+                (Some("days"), Ok(n)) if !n.is_empty() => ContactSchedule::Days {
+                    period: n[0],
+                    days: n[1..].to_vec(),
+                },
+                (Some("random"), _) if fields.len() == 6 => {
+                    ContactSchedule::Random(fields[5].parse().map_err(|_| invalid())?)
+                }
+                _ => return Err(invalid()),
+            };
+
+            network.add_contact(
+                Contact::new(a, b, weight, schedule, fields[3]).map_err(|_| invalid())?,
+            );
+        }
+        Ok(network)
+    }
+
+    /// Runs an outbreak seeded by exposing `seed` on the `start` day, isolating people sit out
+    /// their contacts
+    pub fn run(&self, seed: usize, start: Time) -> Result<Outbreak, Error> {
+        self.run_on_agenda(seed, start, Agenda::new())
+    }
+
+    /// Same as `run`, waking up on the days the given agenda says rather than only on those
+    /// where something can happen
+    fn run_on_agenda(
+        &self,
+        seed: usize,
+        start: Time,
+        mut agenda: Agenda,
+    ) -> Result<Outbreak, Error> {
+        if seed >= self.n_people {
+            return Err(Error::UnknownPerson {
+                person: seed,
+                n_people: self.n_people,
+            });
+        }
+        let mut people: Vec<Person> = (0..self.n_people)
+            .map(|i| {
+                Person::new()
+                    .with_id(i)
+                    .with_isolation_policy(self.isolation_policy)
+            })
+            .collect();
+        let mut infected_in: Vec<Option<&str>> = vec![None; self.n_people];
+        people[seed].try_expose(start)?;
+
+        agenda.schedule(start);
+        while let Some(day) = agenda.next() {
+            if day >= HORIZON || people.iter().all(|p| p.has_recovered(day)) {
+                break;
+            }
+
+            for p in &mut people {
+                p.update_isolation(day);
+            }
+
+            for contact in &self.contacts {
+                let (a, b) = (contact.a, contact.b);
+                // random meetings are only drawn when they can pass the virus on, so that idle
+                // days stay idle
+                if a == b
+                    || people[a].is_isolating(day)
+                    || people[b].is_isolating(day)
+                    || !(people[a].is_contagious(day) || people[b].is_contagious(day))
+                    || !contact.schedule.meets(day)
+                {
+                    continue;
+                }
+
+                let was_sick = (people[a].was_sick(day), people[b].was_sick(day));
+                let (p_a, p_b) = if a < b {
+                    let (left, right) = people.split_at_mut(b);
+                    (&mut left[a], &mut right[0])
+                } else {
+                    let (left, right) = people.split_at_mut(a);
+                    (&mut right[0], &mut left[b])
+                };
+                p_a.interact_with_probability(day, p_b, contact.weight);
+
+                if !was_sick.0 && people[a].was_sick(day) {
+                    infected_in[a] = Some(&contact.setting);
+                }
+                if !was_sick.1 && people[b].was_sick(day) {
+                    infected_in[b] = Some(&contact.setting);
+                }
+            }
+
+            if people.iter().any(|p| p.is_contagious(day)) {
+                agenda.schedule(day + 1);
+            }
+            agenda.schedule_people(day, &people);
+        }
+
+        // attack rate per setting, among everyone but the seed
+        let mut exposed: HashMap<&str, (usize, usize)> = HashMap::new();
+        for person in (0..self.n_people).filter(|p| *p != seed) {
+            let mut settings: Vec<&str> = self
+                .contacts
+                .iter()
+                .filter(|c| c.a == person || c.b == person)
+                .map(|c| c.setting.as_str())
+                .collect();
+            settings.sort_unstable();
+            settings.dedup();
+            for setting in settings {
+                let entry = exposed.entry(setting).or_insert((0, 0));
+                entry.0 += 1;
+                if infected_in[person] == Some(setting) {
+                    entry.1 += 1;
+                }
+            }
+        }
+
+        Ok(Outbreak {
+            final_size: people
+                .iter()
+                .filter(|p| p.get_infection().is_some())
+                .count(),
+            attack_rates: exposed
+                .into_iter()
+                .map(|(s, (n, infected))| (s.to_string(), infected as f64 / n as f64))
+                .collect(),
+            detection: people
+                .iter()
+                .filter_map(|p| p.detection(HORIZON))
+                .map(|d| d.day)
+                .min()
+                .map(|d| d - start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reseed;
+
+    #[test]
+    fn edge_list() {
+        let edges = "# a b weight setting schedule\n\
+                     0 1 1 home\n\
+                     1 2 0.5 school days 7 0 1 2 3 4\n\
+                     \n\
+                     2 3 0.1 sports every 7 5\n\
+                     0 3 0.2 other random 0.1\n";
+        let network = ContactNetwork::from_edge_list(edges.as_bytes()).unwrap();
+        assert_eq!(network.n_people(), 4);
+        assert_eq!(network.contacts[0].schedule, ContactSchedule::Daily);
+        assert_eq!(
+            network.contacts[1].schedule,
+            ContactSchedule::Days {
+                period: 7,
+                days: vec![0, 1, 2, 3, 4]
+            }
+        );
+        assert_eq!(
+            network.contacts[2].schedule,
+            ContactSchedule::Every {
+                period: 7,
+                offset: 5
+            }
+        );
+        assert_eq!(network.contacts[3].schedule, ContactSchedule::Random(0.1));
+
+        assert!(ContactNetwork::from_edge_list("0 1 home".as_bytes()).is_err());
+        assert!(ContactNetwork::from_edge_list("0 1 1 home weekly".as_bytes()).is_err());
+        assert!(ContactNetwork::from_edge_list("0 1 2 home".as_bytes()).is_err());
+        assert!(ContactNetwork::from_edge_list("0 1 NaN home".as_bytes()).is_err());
+        assert!(ContactNetwork::from_edge_list("0 1 1 home random 1.5".as_bytes()).is_err());
+        assert!(ContactNetwork::from_edge_list("0 1 1 home every 0".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn network_outbreak() {
+        let mut network = ContactNetwork::new();
+        network.add_contact(Contact::new(0, 1, 1., ContactSchedule::Daily, "home").unwrap());
+        network.add_contact(Contact::new(1, 2, 0., ContactSchedule::Daily, "school").unwrap());
+        assert_eq!(
+            network.run(3, 10),
+            Err(Error::UnknownPerson {
+                person: 3,
+                n_people: 3
+            })
+        );
+        assert!(Contact::new(0, 1, 1., ContactSchedule::Random(2.), "home").is_err());
+        for weight in &[-0.1, 1.5, f64::NAN] {
+            assert!(Contact::new(0, 1, *weight, ContactSchedule::Daily, "home").is_err());
+        }
+
+        // released people can't be told apart in the summary, the policy only has to get there
+        let released = network
+            .clone()
+            .with_isolation_policy(IsolationPolicy::Fixed(7));
+        assert_eq!(released.isolation_policy, IsolationPolicy::Fixed(7));
+        assert!(released.run(0, 10).is_ok());
+
+        for _ in 0..1_000 {
+            let outbreak = network.run(0, 10).unwrap();
+            assert!(outbreak.final_size == 1 || outbreak.final_size == 2);
+            assert_eq!(outbreak.attack_rates["school"], 0.);
+            assert_eq!(
+                outbreak.attack_rates["home"],
+                (outbreak.final_size - 1) as f64
+            );
+        }
+    }
+
+    #[test]
+    fn agenda_matches_daily_loop() {
+        let mut network = ContactNetwork::new();
+        network.add_contact(Contact::new(0, 1, 0.5, ContactSchedule::Daily, "home").unwrap());
+        network.add_contact(Contact::new(1, 2, 0.5, ContactSchedule::Random(0.3), "bus").unwrap());
+        let weekly = ContactSchedule::Days {
+            period: 7,
+            days: vec![0, 3],
+        };
+        network.add_contact(Contact::new(2, 3, 0.8, weekly, "sports").unwrap());
+
+        // same seed, same draws, as long as the skipped days are really idle
+        for seed in 0..200 {
+            let run = |agenda| {
+                reseed(seed);
+                network.run_on_agenda(0, 5, agenda).unwrap()
+            };
+            assert_eq!(run(Agenda::new()), run(Agenda::daily()));
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Searching rotation schedules for the best trade-offs between objectives
+
+use crate::{gen_phase_fn, Error, Phase, TestingSchedule, Time};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// A rotation schedule: B spends `a` days with A, `ac` isolating, `c` days with C and `ca`
+/// isolating again, shifted by `offset` days
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Schedule {
+    /// Days with A
+    pub a: Time,
+    /// Isolation days going from A to C
+    pub ac: Time,
+    /// Days with C
+    pub c: Time,
+    /// Isolation days going from C to A
+    pub ca: Time,
+    /// Shift of the cycle
+    pub offset: Time,
+}
+
+impl Schedule {
+    /// Creates a schedule
+    pub fn new(a: Time, ac: Time, c: Time, ca: Time, offset: Time) -> Schedule {
+        Schedule {
+            a,
+            ac,
+            c,
+            ca,
+            offset,
+        }
+    }
+
+    /// Length of a full cycle
+    pub fn cycle_len(&self) -> Time {
+        self.a + self.ac + self.c + self.ca
+    }
+
+    /// Isolation days in a full cycle
+    pub fn isolation_days(&self) -> Time {
+        self.ac + self.ca
+    }
+
+    /// The phase function following this schedule, failing when the cycle has no days
+    pub fn phase_fn(&self) -> Result<Box<dyn Fn(Time) -> Phase + Send + Sync>, Error> {
+        gen_phase_fn(self.a, self.ac, self.c, self.ca, self.offset)
+    }
+}
+
+/// Requirements every schedule has to meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constraints {
+    /// Most isolation days allowed in a cycle
+    pub max_isolation_days: Time,
+    /// Can isolation days fall on a weekend?
+    pub weekend_isolation: bool,
+    /// Fewest days B has to spend with each of A and C in a cycle
+    pub min_days_each: Time,
+    /// Longest cycle, in weeks
+    pub max_weeks: Time,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Constraints {
+            max_isolation_days: 10,
+            weekend_isolation: false,
+            min_days_each: 1,
+            max_weeks: 2,
+        }
+    }
+}
+
+impl Constraints {
+    /// Does the schedule meet every constraint?
+    pub fn allows(&self, schedule: &Schedule) -> bool {
+        if schedule.isolation_days() > self.max_isolation_days
+            || schedule.a < self.min_days_each
+            || schedule.c < self.min_days_each
+            || schedule.cycle_len() > 7 * self.max_weeks
+        {
+            return false;
+        }
+
+        // cycles drift through the week, check them all
+        let phase_fn = match schedule.phase_fn() {
+            Ok(phase_fn) => phase_fn,
+            Err(_) => return false,
+        };
+        self.weekend_isolation
+            || (0..7 * schedule.cycle_len())
+                .filter(|d| d % 7 == 5 || d % 7 == 6)
+                .all(|d| phase_fn(d) != Phase::Isolate)
+    }
+
+    /// Every allowed schedule, keeping only one of those following the same day-by-day phases
+    pub fn candidates(&self) -> Vec<Schedule> {
+        let mut seen = HashSet::new();
+        let mut candidates = Vec::new();
+        for len in 2..=7 * self.max_weeks {
+            for isolation in 0..=self.max_isolation_days.min(len) {
+                for ac in 0..=isolation {
+                    for a in self.min_days_each..=len - isolation {
+                        let c = len - isolation - a;
+                        for offset in 0..7.min(len) {
+                            let schedule = Schedule::new(a, ac, c, isolation - ac, offset);
+                            if !self.allows(&schedule) {
+                                continue;
+                            }
+
+                            let phase_fn =
+                                schedule.phase_fn().expect("allowed schedules have days");
+                            let phases: Vec<Phase> = (0..7 * len).map(&phase_fn).collect();
+                            if seen.insert(phases) {
+                                candidates.push(schedule);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Mean of an objective over a set of trials, and its standard error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Sample mean
+    pub mean: f64,
+    /// Standard error of the mean
+    pub stderr: f64,
+}
+
+impl Estimate {
+    /// Estimates the mean from the given samples
+    pub fn from_samples(samples: &[f64]) -> Estimate {
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let var = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.).max(1.);
+        Estimate {
+            mean,
+            stderr: (var / n).sqrt(),
+        }
+    }
+}
+
+/// Is `a` at least as low as `b` on every objective, and lower on at least one?
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a <= b) && a.iter().zip(b).any(|(a, b)| a < b)
+}
+
+/// The items that no other item dominates, every objective being minimized
+pub fn pareto_front<T: Clone>(scored: &[(T, Vec<f64>)]) -> Vec<(T, Vec<f64>)> {
+    scored
+        .iter()
+        .filter(|(_, x)| !scored.iter().any(|(_, y)| dominates(y, x)))
+        .cloned()
+        .collect()
+}
+
+/// Searches the candidates for the Pareto front of the objectives returned by `evaluate`, all of
+/// them minimized
+///
+/// Each round evaluates the remaining candidates with the given number of trials, then drops
+/// those that another candidate beats on every objective by more than two standard errors. The
+/// front is computed on the last round's means.
+pub fn optimize<F>(
+    candidates: Vec<Schedule>,
+    rounds: &[u64],
+    evaluate: F,
+) -> Vec<(Schedule, Vec<Estimate>)>
+where
+    F: Fn(&Schedule, u64) -> Vec<Estimate> + Sync,
+{
+    let mut remaining: Vec<(Schedule, Vec<Estimate>)> =
+        candidates.into_iter().map(|s| (s, Vec::new())).collect();
+
+    for n in rounds {
+        remaining = remaining
+            .into_par_iter()
+            .map(|(s, _)| (s, evaluate(&s, *n)))
+            .collect();
+
+        let worst: Vec<Vec<f64>> = remaining
+            .iter()
+            .map(|(_, e)| e.iter().map(|e| e.mean + 2. * e.stderr).collect())
+            .collect();
+        remaining.retain(|(_, x)| {
+            let best: Vec<f64> = x.iter().map(|e| e.mean - 2. * e.stderr).collect();
+            !worst
+                .iter()
+                .any(|y| y.iter().zip(&best).all(|(y, b)| y < b))
+        });
+    }
+
+    let means: Vec<(Schedule, Vec<f64>)> = remaining
+        .iter()
+        .map(|(s, e)| (*s, e.iter().map(|e| e.mean).collect()))
+        .collect();
+    let front: HashSet<Schedule> = pareto_front(&means).into_iter().map(|(s, _)| s).collect();
+    remaining
+        .into_iter()
+        .filter(|(s, _)| front.contains(s))
+        .collect()
+}
+
+/// Ranks testing schedules by how much they lower each objective returned by `evaluate`, compared
+/// to nobody testing. The biggest cuts on the first objective come first, ties are broken on the
+/// following ones
+pub fn rank_testing<F>(
+    candidates: Vec<TestingSchedule>,
+    n: u64,
+    evaluate: F,
+) -> Vec<(TestingSchedule, Vec<Estimate>)>
+where
+    F: Fn(&TestingSchedule, u64) -> Vec<Estimate> + Sync,
+{
+    let baseline = evaluate(&TestingSchedule::default(), n);
+    let mut ranked: Vec<(TestingSchedule, Vec<Estimate>)> = candidates
+        .into_par_iter()
+        .map(|testing| {
+            let cuts = baseline
+                .iter()
+                .zip(evaluate(&testing, n))
+                .map(|(b, e)| Estimate {
+                    mean: b.mean - e.mean,
+                    stderr: b.stderr.hypot(e.stderr),
+                })
+                .collect();
+            (testing, cuts)
+        })
+        .collect();
+
+    ranked.sort_by(|(_, x), (_, y)| {
+        x.iter()
+            .zip(y)
+            .map(|(x, y)| y.mean.partial_cmp(&x.mean).unwrap_or(Ordering::Equal))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    ranked
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pareto() {
+        let scored = vec![
+            ("a", vec![1., 5.]),
+            ("b", vec![2., 2.]),
+            ("c", vec![3., 3.]),
+            ("d", vec![5., 1.]),
+            ("e", vec![2., 2.]),
+        ];
+        let front: Vec<&str> = pareto_front(&scored).iter().map(|(n, _)| *n).collect();
+        assert_eq!(front, vec!["a", "b", "d", "e"]);
+    }
+
+    #[test]
+    fn constraints() {
+        let constraints = Constraints::default();
+        assert!(constraints.allows(&Schedule::new(1, 0, 1, 0, 0)));
+        assert!(!constraints.allows(&Schedule::new(14, 0, 0, 0, 0)));
+        assert!(!constraints.allows(&Schedule::new(5, 2, 5, 2, 0)));
+        assert!(!constraints.allows(&Schedule::new(5, 2, 5, 2, 1)));
+        assert!(constraints.allows(&Schedule::new(6, 1, 6, 1, 6)));
+
+        let candidates = constraints.candidates();
+        assert!(candidates.iter().all(|s| constraints.allows(s)));
+        assert!(candidates.contains(&Schedule::new(1, 0, 1, 0, 0)));
+        assert!(!candidates.contains(&Schedule::new(1, 0, 1, 0, 2)));
+    }
+
+    #[test]
+    fn rank_testing_by_cuts() {
+        let candidates = TestingSchedule::within_budget(&[0], &[crate::TestKind::PCR], 2);
+
+        // each test cuts infections by one, testing early in the week cuts days unaware
+        let ranked = rank_testing(candidates, 1, |testing, _| {
+            let days: f64 = (0..7)
+                .map(|d| testing.tests_on(d).count() as f64 * d as f64)
+                .sum();
+            vec![
+                Estimate {
+                    mean: 10. - testing.per_week() as f64,
+                    stderr: 0.,
+                },
+                Estimate {
+                    mean: days,
+                    stderr: 0.,
+                },
+            ]
+        });
+        assert_eq!(ranked[0].0.describe(), "0@0:pcr 0@1:pcr");
+        assert_eq!(ranked[0].1[0].mean, 2.);
+        assert_eq!(ranked.last().unwrap().0.describe(), "0@6:pcr");
+    }
+
+    #[test]
+    fn optimize_front() {
+        let candidates = vec![
+            Schedule::new(7, 0, 7, 0, 0),
+            Schedule::new(6, 1, 6, 1, 0),
+            Schedule::new(5, 2, 5, 2, 0),
+            Schedule::new(1, 0, 1, 0, 0),
+        ];
+
+        // fewer isolation days against fewer handovers, the alternating schedule is dominated
+        let front = optimize(candidates, &[10, 100], |s, _| {
+            let handovers = 14. / s.cycle_len() as f64;
+            vec![
+                Estimate {
+                    mean: s.isolation_days() as f64,
+                    stderr: 0.,
+                },
+                Estimate {
+                    mean: if s.a == 1 {
+                        20.
+                    } else {
+                        handovers - s.ac as f64
+                    },
+                    stderr: 0.,
+                },
+            ]
+        });
+        let schedules: Vec<Schedule> = front.iter().map(|(s, _)| *s).collect();
+        assert_eq!(
+            schedules,
+            vec![
+                Schedule::new(7, 0, 7, 0, 0),
+                Schedule::new(6, 1, 6, 1, 0),
+                Schedule::new(5, 2, 5, 2, 0)
+            ]
+        );
+    }
+}
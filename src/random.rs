@@ -0,0 +1,90 @@
+//! Random numbers behind every simulation, which can be reseeded to make runs reproducible
+
+use rand::rngs::StdRng;
+use rand::{Error as RandError, RngCore, SeedableRng};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+thread_local! {
+    static RNG: Rc<RefCell<StdRng>> = Rc::new(RefCell::new(StdRng::from_entropy()));
+}
+
+/// Handle on the random number generator of the current thread. Seeded from the OS unless
+/// `reseed` says otherwise
+#[derive(Debug, Clone)]
+pub struct SimRng(Rc<RefCell<StdRng>>);
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.borrow_mut().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.borrow_mut().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.borrow_mut().fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), RandError> {
+        self.0.borrow_mut().try_fill_bytes(dest)
+    }
+}
+
+/// The random number generator of the current thread
+pub fn rng() -> SimRng {
+    SimRng(RNG.with(Rc::clone))
+}
+
+/// Restarts the random number generator of the current thread from the given seed, everything
+/// it runs from then on being reproducible
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Seed of one of many independent streams drawn from a single seed, mixed with SplitMix64 so
+/// neighbouring streams look nothing alike
+pub fn stream_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Person;
+    use rand::Rng;
+
+    #[test]
+    fn reproducible() {
+        let draws = |seed| {
+            reseed(seed);
+            let mut rng = rng();
+            (0..10).map(|_| rng.gen::<u64>()).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(1), draws(1));
+        assert_ne!(draws(1), draws(2));
+
+        // handles share the same generator
+        reseed(3);
+        let (mut a, mut b) = (rng(), rng());
+        let first = (a.gen::<u64>(), b.gen::<u64>());
+        reseed(3);
+        let mut c = rng();
+        assert_eq!(first, (c.gen::<u64>(), c.gen::<u64>()));
+
+        let infection = |seed| {
+            reseed(seed);
+            let mut me = Person::new();
+            me.expose(100);
+            format!("{:?}", me.get_infection())
+        };
+        assert_eq!(infection(4), infection(4));
+
+        assert_ne!(stream_seed(0, 0), stream_seed(0, 1));
+        assert_ne!(stream_seed(0, 1), stream_seed(1, 0));
+    }
+}
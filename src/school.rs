@@ -0,0 +1,464 @@
+//! Households, classrooms and pods, and the weekly schedules connecting them
+
+use crate::{
+    run_single, AgeGroup, CyclicPattern, DailyAction, Disease, Error, Households, IsolationPolicy,
+    IsolationScope, PatternDesc, Resolution, SymptomModel, TestKind, Time,
+};
+use std::collections::{HashMap, HashSet};
+
+/// How children attend school over the two weeks of the schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchoolWeeks {
+    /// Every child goes to school every weekday
+    #[default]
+    Full,
+
+    /// Pods take turns, even pods go to school the first week and odd pods the second one
+    Hybrid,
+
+    /// Nobody goes to school
+    Remote,
+}
+
+/// A population of households whose children go to school together
+#[derive(Debug, Clone)]
+pub struct School {
+    n_households: usize,
+    adults: usize,
+    children: usize,
+    classroom_size: usize,
+    pods: usize,
+    weeks: SchoolWeeks,
+    shared_custody: bool,
+    scope: IsolationScope,
+    pooled_tests: Option<(Time, TestKind)>,
+    disease: Disease,
+    resolution: Resolution,
+    isolation_policy: IsolationPolicy,
+    symptoms: SymptomModel,
+}
+
+/// Who got infected in a single outbreak through the school
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchoolOutbreak {
+    /// Number of children infected
+    pub children_infected: usize,
+
+    /// Number of adults infected
+    pub adults_infected: usize,
+
+    /// Number of households with at least one infection
+    pub households_infected: usize,
+
+    /// Number of tests used, pools and reflex tests included
+    pub tests_used: usize,
+}
+
+impl School {
+    /// Creates a school for the given number of two-adult, one-child households, with classrooms
+    /// of the given size split in two pods, failing on empty classrooms
+    pub fn new(n_households: usize, classroom_size: usize) -> Result<School, Error> {
+        if classroom_size == 0 {
+            return Err(Error::InvalidParameter("classroom size of 0".to_string()));
+        }
+        Ok(School {
+            n_households,
+            adults: 2,
+            children: 1,
+            classroom_size,
+            pods: 2,
+            weeks: SchoolWeeks::default(),
+            shared_custody: false,
+            scope: IsolationScope::Household,
+            pooled_tests: None,
+            disease: Disease::default(),
+            resolution: Resolution::DAY,
+            isolation_policy: IsolationPolicy::default(),
+            symptoms: SymptomModel::default(),
+        })
+    }
+
+    /// Sets the number of adults and children in each household
+    pub fn household_size(mut self, adults: usize, children: usize) -> School {
+        self.adults = adults;
+        self.children = children;
+        self
+    }
+
+    /// Splits each classroom in the given number of pods, failing on 0
+    pub fn with_pods(mut self, pods: usize) -> Result<School, Error> {
+        if pods == 0 {
+            return Err(Error::InvalidParameter("0 pods".to_string()));
+        }
+        self.pods = pods;
+        Ok(self)
+    }
+
+    /// Sets how children attend school
+    pub fn with_weeks(mut self, weeks: SchoolWeeks) -> School {
+        self.weeks = weeks;
+        self
+    }
+
+    /// Pairs up households, children spending the second week with the other household, and
+    /// counting as part of it for isolation
+    pub fn shared_custody(mut self, shared_custody: bool) -> School {
+        self.shared_custody = shared_custody;
+        self
+    }
+
+    /// Sets how far isolation extends around someone who isolates
+    pub fn with_scope(mut self, scope: IsolationScope) -> School {
+        self.scope = scope;
+        self
+    }
+
+    /// Tests every pod as a single pool, once a week on the given weekday
+    pub fn pooled_tests(mut self, weekday: Time, kind: TestKind) -> School {
+        self.pooled_tests = Some((weekday, kind));
+        self
+    }
+
+    /// Sets the disease parameters of children and adults
+    pub fn with_disease(mut self, disease: Disease) -> School {
+        self.disease = disease;
+        self
+    }
+
+    /// Splits days in the given number of steps: pools are then tested at 7am, before class
+    /// starts at 8am, and households get together at 6pm
+    pub fn with_resolution(mut self, resolution: Resolution) -> School {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Sets the rules deciding when people stop isolating
+    pub fn with_isolation_policy(mut self, isolation_policy: IsolationPolicy) -> School {
+        self.isolation_policy = isolation_policy;
+        self
+    }
+
+    /// Sets how everyone reacts to symptoms, and how often they get colds
+    pub fn with_symptoms(mut self, symptoms: SymptomModel) -> School {
+        self.symptoms = symptoms;
+        self
+    }
+
+    /// Number of people across all households
+    pub fn n_people(&self) -> usize {
+        self.n_households * (self.adults + self.children)
+    }
+
+    /// Household someone lives in
+    fn household(&self, person: usize) -> usize {
+        person / (self.adults + self.children)
+    }
+
+    /// Is this person a child?
+    fn is_child(&self, person: usize) -> bool {
+        person % (self.adults + self.children) >= self.adults
+    }
+
+    /// Household a child stays with in the given week of the schedule
+    fn home(&self, child: usize, week: Time) -> usize {
+        let home = self.household(child);
+        let partner = home ^ 1;
+        if self.shared_custody && week == 1 && partner < self.n_households {
+            partner
+        } else {
+            home
+        }
+    }
+
+    /// Builds the two-week pattern of interactions at home and at school
+    pub fn pattern(&self) -> PatternDesc {
+        let children: Vec<usize> = (0..self.n_people()).filter(|p| self.is_child(*p)).collect();
+        let r = self.resolution;
+        let mut actions: HashMap<Time, Vec<DailyAction>> = HashMap::new();
+
+        for day in 0..14 {
+            let week = day / 7;
+            let at = |hour: Time| r.days(day) + r.steps_per_day() * hour / 24;
+            let mut moves = Vec::new();
+            let mut home = Vec::new();
+            let mut school = Vec::new();
+            let mut tests = Vec::new();
+
+            // children change homes at school at the start of each week
+            if day % 7 == 0 {
+                for c in &children {
+                    if self.home(*c, 0) != self.home(*c, 1) {
+                        moves.push(DailyAction::Move(*c, self.home(*c, week)));
+                    }
+                }
+            }
+
+            // everyone staying together meets every day
+            for h in 0..self.n_households {
+                let adults = (0..self.adults).map(|a| h * (self.adults + self.children) + a);
+                let kids = children
+                    .iter()
+                    .copied()
+                    .filter(|c| self.home(*c, week) == h);
+                all_pairs(&adults.chain(kids).collect::<Vec<_>>(), &mut home);
+            }
+
+            // everyone in class on weekdays
+            if day % 7 < 5 {
+                for classroom in children.chunks(self.classroom_size) {
+                    let present: Vec<usize> = classroom
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| match self.weeks {
+                            SchoolWeeks::Full => true,
+                            SchoolWeeks::Hybrid => (*i % self.pods) as Time % 2 == week,
+                            SchoolWeeks::Remote => false,
+                        })
+                        .map(|(_, c)| *c)
+                        .collect();
+                    all_pairs(&present, &mut school);
+                }
+            }
+
+            // weekly pooled tests, one pool per pod
+            if let Some((weekday, kind)) = self.pooled_tests {
+                if day % 7 == weekday {
+                    for classroom in children.chunks(self.classroom_size) {
+                        for pod in 0..self.pods.min(classroom.len()) {
+                            let pool = classroom.iter().skip(pod).step_by(self.pods);
+                            tests.push(DailyAction::PoolTest(pool.copied().collect(), kind));
+                        }
+                    }
+                }
+            }
+
+            // at whole days, everything happens at once
+            for (hour, today) in [(8, moves), (18, home), (8, school), (7, tests)] {
+                actions.entry(at(hour)).or_default().extend(today);
+            }
+        }
+
+        let mut households =
+            Households::new((0..self.n_people()).map(|p| self.household(p)).collect());
+        if self.shared_custody {
+            for h in (0..self.n_households.saturating_sub(1)).step_by(2) {
+                households.link(h, h + 1);
+            }
+        }
+
+        let cycle = CyclicPattern::new(r.days(14), 0, actions);
+        PatternDesc::new(self.n_people(), vec![cycle])
+            .with_households(households, self.scope)
+            .with_resolution(r)
+            .with_ages(
+                (0..self.n_people())
+                    .map(|p| {
+                        if self.is_child(p) {
+                            AgeGroup::Child
+                        } else {
+                            AgeGroup::Adult
+                        }
+                    })
+                    .collect(),
+            )
+            .with_disease(self.disease.clone())
+            .with_isolation_policy(self.isolation_policy)
+            .with_symptoms(vec![self.symptoms; self.n_people()])
+    }
+
+    /// Runs a single outbreak started by exposing `seed` at the start of the `start` day
+    pub fn run(&self, seed: usize, start: Time) -> Result<SchoolOutbreak, Error> {
+        let start = self.resolution.days(start);
+        let pattern = self.pattern();
+        pattern.validate()?;
+        let run = run_single(&pattern, seed, start, false, None)?;
+        let people = run.people;
+        let infected: Vec<usize> = (0..people.len())
+            .filter(|p| people[*p].get_infection().is_some())
+            .collect();
+
+        Ok(SchoolOutbreak {
+            children_infected: infected.iter().filter(|p| self.is_child(**p)).count(),
+            adults_infected: infected.iter().filter(|p| !self.is_child(**p)).count(),
+            households_infected: infected
+                .iter()
+                .map(|p| self.household(*p))
+                .collect::<HashSet<_>>()
+                .len(),
+            tests_used: run.tests_used,
+        })
+    }
+}
+
+/// Adds an interaction between every pair of the given people
+fn all_pairs(people: &[usize], actions: &mut Vec<DailyAction>) {
+    for (i, a) in people.iter().enumerate() {
+        for b in &people[i + 1..] {
+            actions.push(DailyAction::Interact(*a, *b));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{trace_single, DiseaseParams, Event, Period, Person};
+
+    /// Every pair meeting on the given day of the pattern
+    fn pairs(pattern: &PatternDesc, day: Time) -> HashSet<(usize, usize)> {
+        pattern.cycles[0].actions[&day]
+            .iter()
+            .filter_map(|a| match a {
+                DailyAction::Interact(a, b) => Some((*a, *b)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn school_pattern() {
+        // 4 households of 1 adult and 1 child, children 1 3 5 7 in one classroom
+        let school = School::new(4, 4).unwrap().household_size(1, 1);
+        let full = school.clone().pattern();
+        assert!(pairs(&full, 0).contains(&(0, 1)));
+        assert!(pairs(&full, 0).contains(&(1, 7)));
+        assert!(!pairs(&full, 5).contains(&(1, 7)));
+        assert_eq!(pairs(&full, 0).len(), 4 + 6);
+        assert_eq!(full.isolation_policy, IsolationPolicy::Indefinite);
+        let released = school
+            .clone()
+            .with_isolation_policy(IsolationPolicy::Fixed(7));
+        assert_eq!(
+            released.pattern().isolation_policy,
+            IsolationPolicy::Fixed(7)
+        );
+        let slow = SymptomModel {
+            action_delay: 2.,
+            ..SymptomModel::default()
+        };
+        assert_eq!(
+            school.clone().with_symptoms(slow).pattern().symptoms,
+            vec![slow; 8]
+        );
+        assert!(School::new(4, 0).is_err());
+        assert!(school.clone().with_pods(0).is_err());
+
+        // pods 1 5 and 3 7 take turns
+        let hybrid = school.clone().with_weeks(SchoolWeeks::Hybrid).pattern();
+        assert!(pairs(&hybrid, 0).contains(&(1, 5)));
+        assert!(!pairs(&hybrid, 0).contains(&(3, 7)));
+        assert!(pairs(&hybrid, 7).contains(&(3, 7)));
+        for across in &[(1, 3), (1, 7), (3, 5), (5, 7)] {
+            assert!(!pairs(&hybrid, 0).contains(across));
+            assert!(!pairs(&hybrid, 7).contains(across));
+        }
+
+        // second week with the other household
+        let custody = school
+            .with_weeks(SchoolWeeks::Remote)
+            .shared_custody(true)
+            .pattern();
+        assert_eq!(
+            pairs(&custody, 0),
+            [(0, 1), (2, 3), (4, 5), (6, 7)].iter().copied().collect()
+        );
+        assert_eq!(
+            pairs(&custody, 7),
+            [(0, 3), (2, 1), (4, 7), (6, 5)].iter().copied().collect()
+        );
+    }
+
+    #[test]
+    fn custody_isolation() {
+        // 4 households of 1 adult and 1 child, children 1 and 3 swap homes the second week
+        let school = School::new(4, 4)
+            .unwrap()
+            .household_size(1, 1)
+            .with_weeks(SchoolWeeks::Remote)
+            .shared_custody(true);
+        let pattern = school.clone().pattern();
+
+        // child 1 isolates with the household they stay with that week, and only that one
+        let mut people: Vec<Person> = (0..8).map(|_| Person::new()).collect();
+        people[1].expose(0);
+        let t = people[1].get_infection().unwrap().testable_date;
+        people[1].test(t, 0);
+        let isolating = |day| -> Vec<usize> {
+            let households = pattern.households_at(day);
+            let isolating = households.isolating(&people, IsolationScope::Household, t);
+            (0..8).filter(|p| isolating[*p]).collect()
+        };
+        assert_eq!(isolating(3), vec![0, 1]);
+        assert_eq!(isolating(7), vec![1, 2]);
+        assert_eq!(isolating(13), vec![1, 2]);
+        assert_eq!(isolating(14), vec![0, 1]);
+
+        // noticing symptoms the day after arriving, child 1 keeps seeing the other household
+        // while adult 0 and child 3 carry on
+        let symptomatic = Disease::uniform(DiseaseParams {
+            incubation: Period::Fixed(1.),
+            symptomatic_fraction: 1.,
+            ..DiseaseParams::default()
+        });
+        let pattern = school.with_disease(symptomatic).pattern();
+        let trace = trace_single(&pattern, 1, 7).unwrap();
+        let met = |a, b, day| {
+            trace
+                .events()
+                .contains(&Event::Interaction { time: day, a, b })
+        };
+        for day in 8..14 {
+            assert!(met(2, 1, day));
+            assert!(met(0, 3, day));
+        }
+        assert!(met(0, 1, 14));
+        assert!(met(2, 3, 14));
+    }
+
+    #[test]
+    fn school_pools() {
+        let school = School::new(4, 4)
+            .unwrap()
+            .household_size(1, 1)
+            .pooled_tests(2, TestKind::PCR);
+        let pattern = school.pattern();
+        let pools: Vec<&Vec<usize>> = pattern.cycles[0].actions[&9]
+            .iter()
+            .filter_map(|a| match a {
+                DailyAction::PoolTest(pool, _) => Some(pool),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pools, vec![&vec![1, 5], &vec![3, 7]]);
+
+        // hour by hour, pools are taken before class, and class is over before dinner at home
+        let hourly = school.clone().with_resolution(Resolution::HOUR).pattern();
+        let day = 9 * 24;
+        assert!(pairs(&hourly, day + 7).is_empty());
+        assert_eq!(hourly.cycles[0].actions[&(day + 7)].len(), 2);
+        assert!(pairs(&hourly, day + 8).contains(&(1, 7)));
+        assert!(!pairs(&hourly, day + 8).contains(&(0, 1)));
+        assert!(pairs(&hourly, day + 18).contains(&(0, 1)));
+        assert!(school
+            .clone()
+            .with_resolution(Resolution::HOUR)
+            .run(1, 2)
+            .is_ok());
+
+        // nobody testable on the first day, every pool comes back negative
+        let outbreak = school.run(1, 2).unwrap();
+        assert!(outbreak.tests_used >= 2);
+    }
+
+    #[test]
+    fn school_outbreak() {
+        let school = School::new(6, 3).unwrap().with_weeks(SchoolWeeks::Remote);
+        for _ in 0..100 {
+            // no school, nothing leaves the household
+            let outbreak = school.run(2, 10).unwrap();
+            assert_eq!(outbreak.households_infected, 1);
+            assert!(outbreak.adults_infected >= 1);
+            assert!(outbreak.children_infected <= 1);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Mean-field compartmental model, to check the agent model against and to explore populations
+//! too large to simulate person by person
+
+use crate::{DiseaseParams, Error, TestKind};
+
+/// Number of integration steps per day
+const STEPS_PER_DAY: u32 = 20;
+
+/// Deterministic SEIR model with presymptomatic spread, isolation on symptoms and random testing.
+/// Durations are exponentially distributed with the same means as the disease parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Seir {
+    /// Infections per day caused by a contagious person in a fully susceptible population
+    pub contact_rate: f64,
+    /// Mean days from exposure to being contagious
+    pub exposed: f64,
+    /// Mean days contagious before symptom onset
+    pub presymptomatic: f64,
+    /// Mean days contagious after symptom onset
+    pub infectious: f64,
+    /// Share of cases showing symptoms
+    pub symptomatic_fraction: f64,
+    /// Share of symptomatic cases isolating at onset
+    pub symptom_isolation: f64,
+    /// Tests per person per day, a rate rather than a share: 2 tests everyone twice a day
+    pub test_rate: f64,
+    /// Probability a test catches a contagious person
+    pub sensitivity: f64,
+}
+
+/// Number of people in each compartment
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SeirState {
+    /// Never infected
+    pub susceptible: f64,
+    /// Infected, not contagious yet
+    pub exposed: f64,
+    /// Contagious, before symptom onset
+    pub presymptomatic: f64,
+    /// Contagious, after symptom onset
+    pub infectious: f64,
+    /// Found through symptoms or a test, no longer spreading
+    pub isolated: f64,
+    /// Done with the infection
+    pub recovered: f64,
+}
+
+impl SeirState {
+    /// A population of `n` with `infected` people just exposed
+    pub fn seeded(n: f64, infected: f64) -> SeirState {
+        SeirState {
+            susceptible: n - infected,
+            exposed: infected,
+            ..SeirState::default()
+        }
+    }
+
+    /// Everyone in the population
+    pub fn total(&self) -> f64 {
+        self.susceptible
+            + self.exposed
+            + self.presymptomatic
+            + self.infectious
+            + self.isolated
+            + self.recovered
+    }
+
+    /// Everyone ever infected
+    pub fn infected(&self) -> f64 {
+        self.total() - self.susceptible
+    }
+
+    /// `self + other * factor`, compartment by compartment
+    fn add(&self, other: &SeirState, factor: f64) -> SeirState {
+        SeirState {
+            susceptible: self.susceptible + other.susceptible * factor,
+            exposed: self.exposed + other.exposed * factor,
+            presymptomatic: self.presymptomatic + other.presymptomatic * factor,
+            infectious: self.infectious + other.infectious * factor,
+            isolated: self.isolated + other.isolated * factor,
+            recovered: self.recovered + other.recovered * factor,
+        }
+    }
+}
+
+impl Seir {
+    /// Model with the mean periods of the given disease parameters, everyone isolating at
+    /// symptom onset and no testing
+    pub fn new(params: &DiseaseParams, contact_rate: f64) -> Result<Seir, Error> {
+        params.validate()?;
+        let incubation = params.incubation.mean()?;
+        let presymptomatic = params.presymptomatic.mean()?;
+        // same as the agent model, nobody is contagious before the latent period is over, and
+        // onset is pushed back to its end, leaving no presymptomatic period
+        let exposed = params.latent.mean()?.max(incubation - presymptomatic);
+        let seir = Seir {
+            contact_rate: contact_rate * params.infectiousness * params.susceptibility,
+            exposed,
+            presymptomatic: (incubation - exposed).max(0.),
+            infectious: params.infectious.mean()?,
+            symptomatic_fraction: params.symptomatic_fraction,
+            symptom_isolation: 1.,
+            test_rate: 0.,
+            sensitivity: 1.,
+        };
+        seir.validate()?;
+        Ok(seir)
+    }
+
+    /// Tests everyone at the given rate, in tests per person per day, with the given kind of
+    /// test, failing when the rate is negative or not finite or the test is invalid
+    pub fn with_testing(mut self, test_rate: f64, kind: TestKind) -> Result<Seir, Error> {
+        kind.validate()?;
+        self.test_rate = test_rate;
+        self.sensitivity = kind.sensitivity;
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Checks every rate, duration and share is in range
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, value) in &[
+            ("contact rate", self.contact_rate),
+            ("test rate", self.test_rate),
+            ("presymptomatic period", self.presymptomatic),
+        ] {
+            if !value.is_finite() || *value < 0. {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, value)));
+            }
+        }
+        for (name, value) in &[
+            ("exposed period", self.exposed),
+            ("infectious period", self.infectious),
+        ] {
+            if !value.is_finite() || *value <= 0. {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, value)));
+            }
+        }
+        for (name, value) in &[
+            ("symptomatic fraction", self.symptomatic_fraction),
+            ("symptom isolation", self.symptom_isolation),
+            ("sensitivity", self.sensitivity),
+        ] {
+            if !(0. ..=1.).contains(value) {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, value)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Basic reproduction number, accounting for isolation and testing
+    pub fn r0(&self) -> f64 {
+        let detection = self.test_rate * self.sensitivity;
+        let isolating = self.symptomatic_fraction * self.symptom_isolation;
+        let presymptomatic = self.presymptomatic / (1. + detection * self.presymptomatic);
+        let reaching_onset = 1. / (1. + detection * self.presymptomatic);
+        let infectious = self.infectious / (1. + detection * self.infectious);
+        self.contact_rate * (presymptomatic + reaching_onset * (1. - isolating) * infectious)
+    }
+
+    /// Rate of change of every compartment
+    fn derivative(&self, s: &SeirState) -> SeirState {
+        let n = s.total();
+        let contagious = s.presymptomatic + s.infectious;
+        let infections = if n > 0. {
+            self.contact_rate * s.susceptible * contagious / n
+        } else {
+            0.
+        };
+        let detection = self.test_rate * self.sensitivity;
+
+        let incubated = s.exposed / self.exposed;
+        // no presymptomatic period means going straight to symptom onset
+        let onsets = if self.presymptomatic > 0. {
+            s.presymptomatic / self.presymptomatic
+        } else {
+            incubated
+        };
+        let presymptomatic = if self.presymptomatic > 0. {
+            incubated - onsets - detection * s.presymptomatic
+        } else {
+            0.
+        };
+        let isolating = self.symptomatic_fraction * self.symptom_isolation;
+        let recoveries = s.infectious / self.infectious;
+
+        SeirState {
+            susceptible: -infections,
+            exposed: infections - incubated,
+            presymptomatic,
+            infectious: onsets * (1. - isolating) - recoveries - detection * s.infectious,
+            isolated: onsets * isolating + detection * contagious,
+            recovered: recoveries,
+        }
+    }
+
+    /// Integrates the model for the given number of days, returning the state at the start of
+    /// every day, `initial` included
+    pub fn run(&self, initial: SeirState, days: u32) -> Vec<SeirState> {
+        let h = 1. / STEPS_PER_DAY as f64;
+        let mut state = initial;
+        let mut states = vec![state];
+        for _ in 0..days {
+            for _ in 0..STEPS_PER_DAY {
+                // Runge-Kutta, 4th order
+                let k1 = self.derivative(&state);
+                let k2 = self.derivative(&state.add(&k1, h / 2.));
+                let k3 = self.derivative(&state.add(&k2, h / 2.));
+                let k4 = self.derivative(&state.add(&k3, h));
+                state = state
+                    .add(&k1, h / 6.)
+                    .add(&k2, h / 3.)
+                    .add(&k3, h / 3.)
+                    .add(&k4, h / 6.);
+            }
+            states.push(state);
+        }
+        states
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Period;
+
+    #[test]
+    fn final_size() {
+        // no symptoms, no testing: the classic SIR final size relation 1 - z = exp(-R0 z)
+        let params = DiseaseParams {
+            symptomatic_fraction: 0.,
+            ..DiseaseParams::default()
+        };
+        let seir = Seir::new(&params, 0.2).unwrap();
+        assert!((seir.r0() - 0.2 * (seir.presymptomatic + 10.)).abs() < 1e-9);
+
+        let n = 1_000_000.;
+        let states = seir.run(SeirState::seeded(n, 10.), 1_000);
+        let last = states.last().unwrap();
+        assert_eq!(states.len(), 1_001);
+        assert!((last.total() - n).abs() < 1e-3);
+        let z = last.infected() / n;
+        assert!((1. - z - (-seir.r0() * z).exp()).abs() < 1e-3, "{}", z);
+    }
+
+    #[test]
+    fn isolation_and_testing() {
+        let params = DiseaseParams {
+            incubation: Period::Fixed(5.),
+            ..DiseaseParams::default()
+        };
+        let seir = Seir::new(&params, 0.3).unwrap();
+        assert_eq!(seir.exposed, 3.);
+        assert_eq!(seir.presymptomatic, 2.);
+
+        let n = 10_000.;
+        let size = |seir: &Seir| seir.run(SeirState::seeded(n, 1.), 365)[365].infected();
+        let tested = seir.with_testing(0.5, TestKind::PCR).unwrap();
+        assert!(tested.r0() < seir.r0());
+        assert!(size(&tested) < size(&seir));
+
+        // below one, outbreaks fizzle out
+        let tested = seir.with_testing(2., TestKind::PCR).unwrap();
+        assert!(tested.r0() < 1.);
+        assert!(size(&tested) < 10.);
+
+        assert!(Seir::new(&params, -1.).is_err());
+        assert!(seir.with_testing(f64::NAN, TestKind::PCR).is_err());
+        assert!(seir.with_testing(-0.5, TestKind::PCR).is_err());
+    }
+
+    #[test]
+    fn long_latent_period() {
+        // onset comes at the end of the latent period, nobody spreads before symptoms
+        let params = DiseaseParams {
+            incubation: Period::Fixed(2.),
+            latent: Period::Fixed(4.),
+            ..DiseaseParams::default()
+        };
+        let seir = Seir::new(&params, 0.3).unwrap();
+        assert_eq!(seir.exposed, 4.);
+        assert_eq!(seir.presymptomatic, 0.);
+        assert!((seir.r0() - 0.3 * 10. * (1. - 0.6)).abs() < 1e-9);
+
+        let states = seir.run(SeirState::seeded(1_000., 1.), 10);
+        assert!(states.iter().all(|s| s.presymptomatic == 0.));
+        assert!(states[10].isolated > 0.);
+    }
+}
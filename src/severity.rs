@@ -0,0 +1,235 @@
+//! Severe outcomes of an infection: hospitalization, intensive care and death
+
+use crate::{Error, Person, Resolution, Time};
+use rand::Rng;
+use std::ops::AddAssign;
+
+/// Risks and timelines of severe outcomes for symptomatic cases
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Severity {
+    /// Probability a symptomatic case ends up in hospital
+    pub hospitalization: f64,
+    /// Probability someone in hospital needs intensive care
+    pub icu: f64,
+    /// Probability someone in hospital dies
+    pub death: f64,
+    /// Days from symptom onset to hospital admission
+    pub onset_to_hospital: Time,
+    /// Days from hospital admission to intensive care
+    pub hospital_to_icu: Time,
+    /// Days from hospital admission to death
+    pub hospital_to_death: Time,
+    /// Share of the hospitalization risk a vaccinated person avoids
+    pub vaccine_efficacy: f64,
+}
+
+impl Severity {
+    /// Severity with the given risks, and typical timelines and vaccine efficacy
+    pub fn typical(hospitalization: f64, icu: f64, death: f64) -> Severity {
+        Severity {
+            hospitalization,
+            icu,
+            death,
+            onset_to_hospital: 7,
+            hospital_to_icu: 2,
+            hospital_to_death: 10,
+            vaccine_efficacy: 0.9,
+        }
+    }
+
+    /// Checks every probability, and the share of risk the vaccine avoids, is in [0, 1]
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, p) in &[
+            ("hospitalization probability", self.hospitalization),
+            ("intensive care probability", self.icu),
+            ("death probability", self.death),
+            ("vaccine efficacy", self.vaccine_efficacy),
+        ] {
+            if !(0. ..=1.).contains(p) {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, p)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Risk of each outcome for someone with symptoms
+    fn risk(&self, vaccinated: bool) -> SevereRisk {
+        let protection = if vaccinated {
+            self.vaccine_efficacy
+        } else {
+            0.
+        };
+        let hospitalization = self.hospitalization * (1. - protection);
+        SevereRisk {
+            hospitalization,
+            icu: hospitalization * self.icu,
+            death: hospitalization * self.death,
+        }
+    }
+
+    /// Draws the severe outcomes of a case whose symptoms started on `onset`, for a validated
+    /// severity
+    pub(crate) fn outcome<R: Rng>(
+        &self,
+        onset: Time,
+        vaccinated: bool,
+        r: Resolution,
+        rng: &mut R,
+    ) -> SevereOutcome {
+        let mut outcome = SevereOutcome::default();
+        if !rng.gen_bool(self.risk(vaccinated).hospitalization) {
+            return outcome;
+        }
+
+        let admission = onset + r.days(self.onset_to_hospital);
+        outcome.hospitalized = Some(admission);
+        if rng.gen_bool(self.icu) {
+            outcome.icu = Some(admission + r.days(self.hospital_to_icu));
+        }
+        if rng.gen_bool(self.death) {
+            outcome.death = Some(admission + r.days(self.hospital_to_death));
+        }
+        outcome
+    }
+}
+
+/// When an infection led to each severe outcome, if it did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SevereOutcome {
+    /// Hospital admission
+    pub hospitalized: Option<Time>,
+    /// Admission to intensive care
+    pub icu: Option<Time>,
+    /// Death
+    pub death: Option<Time>,
+}
+
+/// Expected number of each severe outcome
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SevereRisk {
+    /// Expected hospitalizations
+    pub hospitalization: f64,
+    /// Expected intensive care admissions
+    pub icu: f64,
+    /// Expected deaths
+    pub death: f64,
+}
+
+impl AddAssign for SevereRisk {
+    fn add_assign(&mut self, other: SevereRisk) {
+        self.hospitalization += other.hospitalization;
+        self.icu += other.icu;
+        self.death += other.death;
+    }
+}
+
+impl Person {
+    /// Sets whether this person is vaccinated, which lowers their risk of severe outcomes
+    pub fn with_vaccination(mut self, vaccinated: bool) -> Person {
+        self.vaccinated = vaccinated;
+        self
+    }
+
+    /// Is this person vaccinated?
+    pub fn is_vaccinated(&self) -> bool {
+        self.vaccinated
+    }
+
+    /// Severe outcomes of this person's infection, if the disease has a severity layer
+    pub fn severe_outcome(&self) -> Option<SevereOutcome> {
+        self.infection.and_then(|i| i.severe)
+    }
+
+    /// Expected severe outcomes of this person's infection so far, nothing if they were never
+    /// infected or never showed symptoms
+    pub fn severe_risk(&self) -> SevereRisk {
+        match (self.infection, self.params.severity) {
+            (Some(infection), Some(severity)) if infection.symptomatic_date.is_some() => {
+                severity.risk(self.vaccinated)
+            }
+            _ => SevereRisk::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AgeGroup, Disease, DiseaseParams};
+
+    #[test]
+    fn severe_outcomes() {
+        let disease = Disease::age_structured();
+        let mut hospitalized = 0;
+        for _ in 0..10_000 {
+            let mut me = Person::new().with_age(AgeGroup::Senior, &disease).unwrap();
+            assert_eq!(me.severe_risk(), SevereRisk::default());
+            me.expose(100);
+
+            let outcome = me.severe_outcome().unwrap();
+            match me.get_infection().unwrap().symptomatic_date {
+                Some(s) => {
+                    if let Some(h) = outcome.hospitalized {
+                        assert_eq!(h, s + 7);
+                        hospitalized += 1;
+                    }
+                    assert!(me.severe_risk().hospitalization > 0.);
+                }
+                None => assert_eq!(outcome, SevereOutcome::default()),
+            }
+            if outcome.hospitalized.is_none() {
+                assert!(outcome.icu.is_none() && outcome.death.is_none());
+            }
+        }
+
+        // 70% symptomatic, 20% of them in hospital
+        assert!((1_200..1_600).contains(&hospitalized), "{}", hospitalized);
+
+        // no severity layer, no outcomes
+        let mut me = Person::new();
+        me.expose(100);
+        assert_eq!(me.severe_outcome(), None);
+    }
+
+    #[test]
+    fn vaccination() {
+        let severity = Severity::typical(0.2, 0.3, 0.25);
+        let unvaccinated = severity.risk(false);
+        let vaccinated = severity.risk(true);
+        assert!((vaccinated.hospitalization - 0.02).abs() < 1e-9);
+        assert!((unvaccinated.death / vaccinated.death - 10.).abs() < 1e-9);
+
+        let mut total = SevereRisk::default();
+        total += unvaccinated;
+        total += vaccinated;
+        assert!((total.hospitalization - 0.22).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_severity() {
+        assert!(Severity::typical(0.2, 0.3, 0.25).validate().is_ok());
+        let invalid = [
+            Severity::typical(f64::NAN, 0.3, 0.25),
+            Severity::typical(1.5, 0.3, 0.25),
+            Severity::typical(0.2, -0.1, 0.25),
+            Severity::typical(0.2, 0.3, f64::NAN),
+            Severity {
+                vaccine_efficacy: f64::INFINITY,
+                ..Severity::typical(0.2, 0.3, 0.25)
+            },
+        ];
+        for severity in &invalid {
+            assert!(matches!(
+                severity.validate(),
+                Err(Error::InvalidParameter(_))
+            ));
+
+            // caught before anyone gets exposed, rather than panicking on the draw
+            let disease = Disease::uniform(DiseaseParams {
+                severity: Some(*severity),
+                ..DiseaseParams::default()
+            });
+            assert!(Person::new().with_age(AgeGroup::Senior, &disease).is_err());
+        }
+    }
+}
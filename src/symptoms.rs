@@ -0,0 +1,252 @@
+//! How people notice and act on symptoms, whether they come from the infection or not
+
+use crate::{rng, Error, Person, Resolution, TestKind, Time};
+use rand::Rng;
+use rand_distr::{Distribution, Exp};
+
+/// How people react to symptoms, and how often they get symptoms unrelated to the infection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SymptomModel {
+    /// Mean number of days between symptom onset and acting on it
+    pub action_delay: f64,
+    /// Probability of never acting on mild symptoms
+    pub ignore: f64,
+    /// Daily probability of non-specific symptoms, e.g. from a cold
+    pub background_rate: f64,
+    /// Days non-specific symptoms last
+    pub background_days: Time,
+    /// Does someone with non-specific symptoms isolate while they last, or until a negative test?
+    pub background_isolation: bool,
+    /// Test taken when acting on symptoms, if any
+    pub test: Option<TestKind>,
+}
+
+impl Default for SymptomModel {
+    /// Symptoms are acted on the day they start, and nobody gets colds
+    fn default() -> Self {
+        SymptomModel {
+            action_delay: 0.,
+            ignore: 0.,
+            background_rate: 0.,
+            background_days: 3,
+            background_isolation: false,
+            test: None,
+        }
+    }
+}
+
+impl SymptomModel {
+    /// Checks the delay is a number of days, every probability is in range and so is the test
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.action_delay.is_finite() || self.action_delay < 0. {
+            return Err(Error::InvalidParameter(format!(
+                "action delay of {}",
+                self.action_delay
+            )));
+        }
+        for (name, p) in &[
+            ("ignore probability", self.ignore),
+            ("background rate", self.background_rate),
+        ] {
+            if !(0. ..=1.).contains(p) {
+                return Err(Error::InvalidParameter(format!("{} of {}", name, p)));
+            }
+        }
+        self.test.map_or(Ok(()), |kind| kind.validate())
+    }
+
+    /// Time someone acts on symptoms that started on `onset`, if they ever do. Fails when the
+    /// probability of ignoring them is out of range
+    pub(crate) fn notice<R: Rng>(
+        &self,
+        onset: Time,
+        r: Resolution,
+        rng: &mut R,
+    ) -> Result<Option<Time>, Error> {
+        if !(0. ..=1.).contains(&self.ignore) {
+            return Err(Error::InvalidParameter(format!(
+                "ignore probability of {}",
+                self.ignore
+            )));
+        }
+        if rng.gen_bool(self.ignore) {
+            return Ok(None);
+        }
+        let delay = match Exp::new(1. / self.action_delay) {
+            Ok(exp) if self.action_delay > 0. => exp.sample(rng) * r.steps_per_day() as f64,
+            _ => 0.,
+        };
+        Ok(Some(onset + delay.round() as Time))
+    }
+
+    /// Time between two episodes of non-specific symptoms
+    fn background_gap<R: Rng>(&self, r: Resolution, rng: &mut R) -> Time {
+        let days = match Exp::new(-(1. - self.background_rate).ln()) {
+            Ok(exp) if self.background_rate < 1. => exp.sample(rng).floor() as Time,
+            _ => 0,
+        };
+        r.days(days + 1)
+    }
+}
+
+impl Person {
+    /// Switches this person to the given symptom model, failing when it is out of range
+    pub fn with_symptoms(mut self, symptoms: SymptomModel) -> Result<Person, Error> {
+        symptoms.validate()?;
+        self.symptoms = symptoms;
+        Ok(self)
+    }
+
+    /// Starts every episode of non-specific symptoms up to the given date, testing if the model
+    /// says so
+    pub(crate) fn update_symptoms(&mut self, date: Time) {
+        if self.symptoms.background_rate <= 0. {
+            return;
+        }
+
+        let mut rng = rng();
+        let r = self.resolution;
+        let mut next = self
+            .next_cold
+            .unwrap_or_else(|| date + self.symptoms.background_gap(r, &mut rng));
+        while next <= date {
+            self.colds.push(next);
+            if let Some(kind) = self.symptoms.test {
+                self.test_for_symptoms(next, kind);
+            }
+            next +=
+                r.days(self.symptoms.background_days) + self.symptoms.background_gap(r, &mut rng);
+        }
+        self.next_cold = Some(next);
+    }
+
+    /// Runs a test of the given kind because of symptoms, rather than a scheduled one
+    pub(crate) fn test_for_symptoms(&mut self, date: Time, kind: TestKind) {
+        self.take_test(date, kind);
+        if let Some(test) = self.tests.last_mut() {
+            test.prompted = true;
+        }
+    }
+
+    /// First time out of isolation for non-specific symptoms starting on `onset`
+    pub(crate) fn cold_isolation_end(&self, onset: Time) -> Time {
+        let r = self.resolution;
+        let symptoms_end = onset + r.days(self.symptoms.background_days);
+        match self.symptoms.test {
+            Some(kind) => symptoms_end.min(onset + r.days(kind.delay)),
+            None => symptoms_end,
+        }
+    }
+
+    /// Is this person isolating because of non-specific symptoms?
+    pub(crate) fn is_isolating_for_cold(&self, date: Time) -> bool {
+        self.symptoms.background_isolation
+            && self
+                .colds
+                .iter()
+                .any(|c| *c <= date && date < self.cold_isolation_end(*c))
+    }
+
+    /// Next time this person gets non-specific symptoms
+    pub(crate) fn next_cold(&self) -> Option<Time> {
+        self.next_cold
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignored_symptoms() {
+        let ignore = SymptomModel {
+            ignore: 1.,
+            ..SymptomModel::default()
+        };
+        for _ in 0..1_000 {
+            let mut me = Person::new().with_symptoms(ignore).unwrap();
+            me.expose(100);
+            assert!(!me.is_isolating(200));
+        }
+    }
+
+    #[test]
+    fn invalid_models() {
+        for ignore in &[-0.1, 1.5, f64::NAN] {
+            let model = SymptomModel {
+                ignore: *ignore,
+                ..SymptomModel::default()
+            };
+            assert!(Person::new().with_symptoms(model).is_err());
+            assert!(matches!(
+                model.notice(10, Resolution::DAY, &mut rng()),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+        let slow = SymptomModel {
+            action_delay: -1.,
+            ..SymptomModel::default()
+        };
+        assert!(Person::new().with_symptoms(slow).is_err());
+        let blunt = SymptomModel {
+            test: Some(TestKind {
+                sensitivity: 2.,
+                ..TestKind::PCR
+            }),
+            ..SymptomModel::default()
+        };
+        assert!(Person::new().with_symptoms(blunt).is_err());
+    }
+
+    #[test]
+    fn delayed_action() {
+        let slow = SymptomModel {
+            action_delay: 2.,
+            test: Some(TestKind::PCR),
+            ..SymptomModel::default()
+        };
+        let mut delays = 0;
+        let mut n = 0;
+        for _ in 0..5_000 {
+            let mut me = Person::new().with_symptoms(slow).unwrap();
+            me.expose(100);
+
+            let infection = me.get_infection().unwrap();
+            if let (Some(s), Some(noticed)) = (infection.symptomatic_date, infection.noticed_date) {
+                assert!(noticed >= s);
+                assert!(!me.is_isolating(noticed - 1));
+                assert!(me.is_isolating(noticed));
+
+                // acting on symptoms means getting tested too
+                assert!(me.tests.iter().any(|t| t.date == noticed));
+                delays += noticed - s;
+                n += 1;
+            }
+        }
+        let mean = delays as f64 / n as f64;
+        assert!((1.7..2.3).contains(&mean), "mean delay {}", mean);
+    }
+
+    #[test]
+    fn background_symptoms() {
+        let colds = SymptomModel {
+            background_rate: 0.1,
+            background_days: 3,
+            background_isolation: true,
+            test: Some(TestKind::PCR),
+            ..SymptomModel::default()
+        };
+        let mut me = Person::new().with_symptoms(colds).unwrap();
+        for day in 0..100 {
+            me.update_isolation(day);
+        }
+        assert!(!me.colds.is_empty());
+
+        // isolating until the negative test comes back, a day later
+        for c in &me.colds {
+            assert!(me.is_isolating(*c));
+            assert!(!me.is_isolating(c + 1));
+            assert!(me.tests.iter().any(|t| t.date == *c && !t.positive));
+        }
+    }
+}
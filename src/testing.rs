@@ -0,0 +1,260 @@
+//! Kinds of tests, pooled testing, and the weekly schedules saying who takes them and when
+
+use crate::{rng, Error, Person, Time};
+use rand::Rng;
+
+/// A kind of test: how long results take, and how likely it is to catch a testable infection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TestKind {
+    /// Short name, for reporting
+    pub name: &'static str,
+    /// Days before the result comes back
+    pub delay: Time,
+    /// Probability of a positive result for a testable infection
+    pub sensitivity: f64,
+    /// Share of the sensitivity lost every time a pool doubles in size
+    pub dilution: f64,
+}
+
+impl TestKind {
+    /// Lab test, results the next day
+    pub const PCR: TestKind = TestKind {
+        name: "pcr",
+        delay: 1,
+        sensitivity: 1.,
+        dilution: 0.05,
+    };
+
+    /// Rapid antigen test, results on the spot but misses some infections
+    pub const RAPID: TestKind = TestKind {
+        name: "rapid",
+        delay: 0,
+        sensitivity: 0.8,
+        dilution: 0.1,
+    };
+
+    /// Sensitivity when testing a pool of the given size
+    pub fn pooled_sensitivity(&self, size: usize) -> f64 {
+        let doublings = (size.max(1) as f64).log2();
+        self.sensitivity * (1. - self.dilution).powf(doublings)
+    }
+
+    /// Checks the sensitivity and dilution are probabilities
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, p) in &[
+            ("sensitivity", self.sensitivity),
+            ("dilution", self.dilution),
+        ] {
+            if !(0. ..=1.).contains(p) {
+                return Err(Error::InvalidParameter(format!(
+                    "{} test {} of {}",
+                    self.name, name, p
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs a single test on the mixed samples of the pool, and reflex individual tests for everyone
+/// in it once a positive pool result comes back. Returns the number of tests used
+pub fn pool_test(
+    people: &mut [Person],
+    pool: &[usize],
+    date: Time,
+    kind: TestKind,
+) -> Result<usize, Error> {
+    let n_people = people.len();
+    if let Some(person) = pool.iter().copied().find(|p| *p >= n_people) {
+        return Err(Error::UnknownPerson { person, n_people });
+    }
+    kind.validate()?;
+    let sensitivity = kind.pooled_sensitivity(pool.len());
+
+    let testable = pool.iter().any(|p| people[*p].is_testable(date));
+    if !testable || !rng().gen_bool(sensitivity) {
+        return Ok(1);
+    }
+
+    for p in pool {
+        let person = &mut people[*p];
+        let reflex = date + person.resolution.days(kind.delay);
+        person.take_test(reflex, kind);
+    }
+    Ok(1 + pool.len())
+}
+
+/// A test someone takes every week
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduledTest {
+    /// Person getting tested
+    pub person: usize,
+    /// Day of the week of the test
+    pub weekday: Time,
+    /// Kind of test
+    pub kind: TestKind,
+}
+
+/// The tests taken every week
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestingSchedule {
+    tests: Vec<ScheduledTest>,
+}
+
+impl TestingSchedule {
+    /// Creates a schedule from the weekly tests
+    pub fn new(tests: Vec<ScheduledTest>) -> TestingSchedule {
+        TestingSchedule { tests }
+    }
+
+    /// Number of tests taken each week
+    pub fn per_week(&self) -> usize {
+        self.tests.len()
+    }
+
+    /// Tests taken on the given day
+    pub fn tests_on(&self, day: Time) -> impl Iterator<Item = &ScheduledTest> {
+        self.tests.iter().filter(move |t| t.weekday == day % 7)
+    }
+
+    /// First day after `date` with a test
+    pub fn next_test(&self, date: Time) -> Option<Time> {
+        (date + 1..=date + 7).find(|d| self.tests_on(*d).next().is_some())
+    }
+
+    /// Every schedule of at most `budget` tests a week among these people and kinds of test,
+    /// nobody taking two tests on the same day
+    pub fn within_budget(
+        people: &[usize],
+        kinds: &[TestKind],
+        budget: usize,
+    ) -> Vec<TestingSchedule> {
+        let slots: Vec<(usize, Time)> = people
+            .iter()
+            .flat_map(|p| (0..7).map(move |d| (*p, d)))
+            .collect();
+
+        let mut schedules = Vec::new();
+        let mut current = Vec::new();
+        choose(&slots, kinds, budget, &mut current, &mut schedules);
+        schedules
+    }
+
+    /// Short description, e.g. `0@1:pcr 1@4:rapid`
+    pub fn describe(&self) -> String {
+        let tests: Vec<String> = self
+            .tests
+            .iter()
+            .map(|t| format!("{}@{}:{}", t.person, t.weekday, t.kind.name))
+            .collect();
+        if tests.is_empty() {
+            "none".to_string()
+        } else {
+            tests.join(" ")
+        }
+    }
+}
+
+/// Adds every schedule extending `current` with tests in the remaining slots
+fn choose(
+    slots: &[(usize, Time)],
+    kinds: &[TestKind],
+    budget: usize,
+    current: &mut Vec<ScheduledTest>,
+    schedules: &mut Vec<TestingSchedule>,
+) {
+    if !current.is_empty() {
+        schedules.push(TestingSchedule::new(current.clone()));
+    }
+    if current.len() == budget {
+        return;
+    }
+
+    for (i, (person, weekday)) in slots.iter().enumerate() {
+        for kind in kinds {
+            current.push(ScheduledTest {
+                person: *person,
+                weekday: *weekday,
+                kind: *kind,
+            });
+            choose(&slots[i + 1..], kinds, budget, current, schedules);
+            current.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn weekly_tests() {
+        let schedule = TestingSchedule::new(vec![
+            ScheduledTest {
+                person: 0,
+                weekday: 1,
+                kind: TestKind::PCR,
+            },
+            ScheduledTest {
+                person: 2,
+                weekday: 4,
+                kind: TestKind::RAPID,
+            },
+        ]);
+        assert_eq!(schedule.tests_on(8).count(), 1);
+        assert_eq!(schedule.tests_on(9).count(), 0);
+        assert_eq!(schedule.next_test(1), Some(4));
+        assert_eq!(schedule.next_test(4), Some(8));
+        assert_eq!(schedule.describe(), "0@1:pcr 2@4:rapid");
+        assert_eq!(TestingSchedule::default().next_test(0), None);
+    }
+
+    #[test]
+    fn pooled() {
+        assert_eq!(TestKind::PCR.pooled_sensitivity(1), 1.);
+        assert!((TestKind::PCR.pooled_sensitivity(8) - 0.95f64.powi(3)).abs() < 1e-9);
+
+        let blunt = TestKind {
+            sensitivity: 1.5,
+            ..TestKind::PCR
+        };
+        let mut people = vec![Person::new(), Person::new()];
+        people[0].expose(0);
+        let t = people[0].get_infection().unwrap().testable_date;
+        assert!(people[0].test_with(t, blunt).is_err());
+        assert!(pool_test(&mut people, &[0, 1], t, blunt).is_err());
+        assert!(people[0].tests.is_empty());
+
+        let perfect = TestKind {
+            name: "perfect",
+            delay: 1,
+            sensitivity: 1.,
+            dilution: 0.,
+        };
+        for _ in 0..1_000 {
+            let mut people = vec![Person::new(), Person::new(), Person::new()];
+            assert_eq!(pool_test(&mut people, &[0, 1, 2], 10, perfect), Ok(1));
+
+            // positive pool, everyone gets retested once the pool result is back
+            people[1].expose(100);
+            let t = people[1].get_infection().unwrap().testable_date;
+            assert_eq!(pool_test(&mut people, &[0, 1, 2], t, perfect), Ok(4));
+            assert_eq!(people[0].tests.len(), 1);
+            assert_eq!(people[0].tests[0].date, t + 1);
+            assert!(people[1].is_isolating(t + 2));
+            assert!(!people[0].is_isolating(t + 2));
+        }
+    }
+
+    #[test]
+    fn budget() {
+        let kinds = [TestKind::PCR, TestKind::RAPID];
+        let one = TestingSchedule::within_budget(&[0, 1], &kinds, 1);
+        assert_eq!(one.len(), 2 * 7 * 2);
+
+        // 14 slots, pick two of them, each with one of two kinds
+        let two = TestingSchedule::within_budget(&[0, 1], &kinds, 2);
+        assert_eq!(two.len(), 28 + 14 * 13 / 2 * 4);
+        assert!(two.iter().all(|s| s.per_week() <= 2));
+    }
+}
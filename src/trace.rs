@@ -0,0 +1,286 @@
+//! Recording of everything that happens during a single simulation run
+
+use crate::{Person, Time};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Something that happened to one or two people at a given time, counted in steps of the
+/// people's resolution, which are days unless said otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The person got infected
+    Exposure {
+        /// Time of the exposure
+        time: Time,
+        /// Person exposed
+        person: usize,
+        /// Person who passed the virus on, if it didn't come from outside
+        source: Option<usize>,
+    },
+
+    /// The person took a test
+    Test {
+        /// Time the test was taken
+        time: Time,
+        /// Person tested
+        person: usize,
+    },
+
+    /// A test result came back
+    Result {
+        /// Time the result came back
+        time: Time,
+        /// Person tested
+        person: usize,
+        /// Was the test positive?
+        positive: bool,
+    },
+
+    /// The person started showing symptoms
+    SymptomOnset {
+        /// Time of onset
+        time: Time,
+        /// Symptomatic person
+        person: usize,
+    },
+
+    /// The person started isolating
+    IsolationStart {
+        /// First time step of isolation
+        time: Time,
+        /// Isolating person
+        person: usize,
+    },
+
+    /// The person stopped isolating
+    IsolationEnd {
+        /// First time step out of isolation
+        time: Time,
+        /// Released person
+        person: usize,
+    },
+
+    /// Two people met
+    Interaction {
+        /// Time of the interaction
+        time: Time,
+        /// First person
+        a: usize,
+        /// Second person
+        b: usize,
+    },
+}
+
+impl Event {
+    /// Time the event happened at
+    pub fn time(&self) -> Time {
+        match *self {
+            Event::Exposure { time, .. }
+            | Event::Test { time, .. }
+            | Event::Result { time, .. }
+            | Event::SymptomOnset { time, .. }
+            | Event::IsolationStart { time, .. }
+            | Event::IsolationEnd { time, .. }
+            | Event::Interaction { time, .. } => time,
+        }
+    }
+}
+
+/// Formats the event as a single line of JSON
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Exposure {
+                time,
+                person,
+                source: Some(source),
+            } => write!(
+                f,
+                r#"{{"time":{},"event":"exposure","person":{},"source":{}}}"#,
+                time, person, source
+            ),
+            Event::Exposure {
+                time,
+                person,
+                source: None,
+            } => write!(
+                f,
+                r#"{{"time":{},"event":"exposure","person":{},"source":null}}"#,
+                time, person
+            ),
+            Event::Test { time, person } => {
+                write!(
+                    f,
+                    r#"{{"time":{},"event":"test","person":{}}}"#,
+                    time, person
+                )
+            }
+            Event::Result {
+                time,
+                person,
+                positive,
+            } => write!(
+                f,
+                r#"{{"time":{},"event":"result","person":{},"positive":{}}}"#,
+                time, person, positive
+            ),
+            Event::SymptomOnset { time, person } => write!(
+                f,
+                r#"{{"time":{},"event":"symptom_onset","person":{}}}"#,
+                time, person
+            ),
+            Event::IsolationStart { time, person } => write!(
+                f,
+                r#"{{"time":{},"event":"isolation_start","person":{}}}"#,
+                time, person
+            ),
+            Event::IsolationEnd { time, person } => write!(
+                f,
+                r#"{{"time":{},"event":"isolation_end","person":{}}}"#,
+                time, person
+            ),
+            Event::Interaction { time, a, b } => write!(
+                f,
+                r#"{{"time":{},"event":"interaction","a":{},"b":{}}}"#,
+                time, a, b
+            ),
+        }
+    }
+}
+
+/// An event log for a single run, in the order things happened
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    events: Vec<Event>,
+}
+
+impl Trace {
+    /// Creates an empty trace
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    /// Adds an event to the trace
+    pub fn record(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Records everything that happened to a person at the given time, should be called once every
+    /// time step after that step's interactions
+    pub fn observe(&mut self, time: Time, person: usize, p: &Person) {
+        if let Some(infection) = p.get_infection() {
+            if infection.date == time {
+                self.record(Event::Exposure {
+                    time,
+                    person,
+                    source: p.infector(),
+                });
+            }
+            if infection.symptomatic_date == Some(time) {
+                self.record(Event::SymptomOnset { time, person });
+            }
+        }
+
+        for t in &p.tests {
+            if t.date == time {
+                self.record(Event::Test { time, person });
+            }
+            if t.result_date == time {
+                self.record(Event::Result {
+                    time,
+                    person,
+                    positive: t.positive,
+                });
+            }
+        }
+
+        let was_isolating = time > 0 && p.is_isolating(time - 1);
+        match (was_isolating, p.is_isolating(time)) {
+            (false, true) => self.record(Event::IsolationStart { time, person }),
+            (true, false) => self.record(Event::IsolationEnd { time, person }),
+            _ => {}
+        }
+    }
+
+    /// All the recorded events
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Writes the trace as JSON Lines, one event per line
+    pub fn write_json_lines<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for event in &self.events {
+            writeln!(w, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_lines() {
+        let mut trace = Trace::new();
+        trace.record(Event::Interaction {
+            time: 3,
+            a: 0,
+            b: 1,
+        });
+        trace.record(Event::Result {
+            time: 4,
+            person: 1,
+            positive: true,
+        });
+        trace.record(Event::Exposure {
+            time: 5,
+            person: 1,
+            source: Some(0),
+        });
+        trace.record(Event::Exposure {
+            time: 5,
+            person: 2,
+            source: None,
+        });
+
+        let mut out = Vec::new();
+        trace.write_json_lines(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"time\":3,\"event\":\"interaction\",\"a\":0,\"b\":1}\n\
+             {\"time\":4,\"event\":\"result\",\"person\":1,\"positive\":true}\n\
+             {\"time\":5,\"event\":\"exposure\",\"person\":1,\"source\":0}\n\
+             {\"time\":5,\"event\":\"exposure\",\"person\":2,\"source\":null}\n"
+        );
+    }
+
+    #[test]
+    fn observe_person() {
+        let mut me = Person::new();
+        me.expose(10);
+        let t = me.get_infection().unwrap().testable_date;
+        me.test(t, 2);
+
+        let mut trace = Trace::new();
+        for time in 10..t + 3 {
+            trace.observe(time, 0, &me);
+        }
+        let events = trace.events();
+        assert_eq!(
+            events[0],
+            Event::Exposure {
+                time: 10,
+                person: 0,
+                source: None
+            }
+        );
+        assert!(events.contains(&Event::Test { time: t, person: 0 }));
+        assert!(events.contains(&Event::Result {
+            time: t + 2,
+            person: 0,
+            positive: true
+        }));
+        assert!(events.windows(2).all(|w| w[0].time() <= w[1].time()));
+    }
+}